@@ -0,0 +1,140 @@
+use wgpu::util::DeviceExt;
+
+/// Number of texels in the lookup texture from [`create_lut_texture`].
+pub const LUT_SIZE: u32 = 256;
+/// Temperature of the first texel, anything colder gets its color.
+pub const LUT_MIN_TEMPERATURE: f32 = 1000.0;
+/// Temperature of the last texel, anything hotter gets its color.
+pub const LUT_MAX_TEMPERATURE: f32 = 40000.0;
+
+// Visible range and step of the spectral integration, in nanometers.
+const WAVELENGTH_MIN: f64 = 360.0;
+const WAVELENGTH_MAX: f64 = 830.0;
+const WAVELENGTH_STEP: f64 = 1.0;
+
+// Planck constant, speed of light and Boltzmann constant in SI units.
+const H: f64 = 6.626_070_15e-34;
+const C: f64 = 2.997_924_58e8;
+const K: f64 = 1.380_649e-23;
+
+/// Linear sRGB color of a blackbody at `kelvin`, scaled so the brightest channel is 1.
+///
+/// Only the hue is kept, the total intensity goes with the fourth power of the temperature.
+pub fn blackbody_rgb(kelvin: f32) -> [f32; 3] {
+    let [x, y, z] = blackbody_xyz(kelvin as f64);
+
+    // XYZ to linear sRGB (D65)
+    let rgb = [
+        3.2406 * x - 1.5372 * y - 0.4986 * z,
+        -0.9689 * x + 1.8758 * y + 0.0415 * z,
+        0.0557 * x - 0.2040 * y + 1.0570 * z,
+    ]
+    // Very hot and very cold blackbodies lie a little outside the sRGB gamut
+    .map(|c| c.max(0.0));
+
+    let max = rgb.iter().copied().fold(0.0, f64::max);
+    if max > 0.0 {
+        rgb.map(|c| (c / max) as f32)
+    } else {
+        [0.0; 3]
+    }
+}
+
+/// Temperature the texel at `index` of the lookup texture holds. Texels are spaced
+/// logarithmically, which keeps them dense where the color changes quickly.
+pub fn lut_temperature(index: u32) -> f32 {
+    let t = index as f32 / (LUT_SIZE - 1) as f32;
+    LUT_MIN_TEMPERATURE * (LUT_MAX_TEMPERATURE / LUT_MIN_TEMPERATURE).powf(t)
+}
+
+/// Upload [`blackbody_rgb`] for [`LUT_SIZE`] temperatures as a `LUT_SIZE`x1 texture.
+///
+/// WebGL2 has no 1D textures, so it is a 2D texture with a single row.
+pub fn create_lut_texture(device: &wgpu::Device, queue: &wgpu::Queue) -> wgpu::Texture {
+    let texels: Vec<u8> = (0..LUT_SIZE)
+        .flat_map(|i| {
+            let [r, g, b] = blackbody_rgb(lut_temperature(i)).map(linear_to_srgb);
+            [r, g, b, 255]
+        })
+        .collect();
+
+    device.create_texture_with_data(
+        queue,
+        &wgpu::TextureDescriptor {
+            size: wgpu::Extent3d {
+                width: LUT_SIZE,
+                height: 1,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            // sRGB encoding spends the 8 bits where the dim channels of red hues need them
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            label: Some("Blackbody"),
+            view_formats: &[],
+        },
+        wgpu::util::TextureDataOrder::MipMajor,
+        &texels,
+    )
+}
+
+pub(crate) fn srgb_to_linear(c: u8) -> f32 {
+    let c = c as f32 / 255.0;
+    if c <= 0.040_45 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+pub(crate) fn linear_to_srgb(c: f32) -> u8 {
+    let encoded = if c <= 0.003_130_8 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    };
+    (encoded.clamp(0.0, 1.0) * 255.0).round() as u8
+}
+
+/// CIE XYZ of the Planck spectrum, up to a constant factor.
+fn blackbody_xyz(kelvin: f64) -> [f64; 3] {
+    let mut xyz = [0.0; 3];
+    let steps = ((WAVELENGTH_MAX - WAVELENGTH_MIN) / WAVELENGTH_STEP) as usize;
+    for i in 0..=steps {
+        let wavelength = WAVELENGTH_MIN + i as f64 * WAVELENGTH_STEP;
+        let radiance = planck(wavelength * 1e-9, kelvin);
+        for (sum, matching) in xyz.iter_mut().zip(color_matching(wavelength)) {
+            *sum += radiance * matching * WAVELENGTH_STEP;
+        }
+    }
+    xyz
+}
+
+/// Spectral radiance of a blackbody at `wavelength` meters.
+fn planck(wavelength: f64, kelvin: f64) -> f64 {
+    let exponent = H * C / (wavelength * K * kelvin);
+    2.0 * H * C * C / (wavelength.powi(5) * exponent.exp_m1())
+}
+
+/// CIE 1931 2° color matching functions at `wavelength` nanometers, from the multi-lobe
+/// Gaussian fit by Wyman, Sloan and Shirley (2013).
+fn color_matching(wavelength: f64) -> [f64; 3] {
+    let lobe = |mean: f64, sigma_below: f64, sigma_above: f64| {
+        let sigma = if wavelength < mean {
+            sigma_below
+        } else {
+            sigma_above
+        };
+        let t = (wavelength - mean) / sigma;
+        (-0.5 * t * t).exp()
+    };
+
+    [
+        1.056 * lobe(599.8, 37.9, 31.0) + 0.362 * lobe(442.0, 16.0, 26.7)
+            - 0.065 * lobe(501.1, 20.4, 26.2),
+        0.821 * lobe(568.8, 46.9, 40.5) + 0.286 * lobe(530.9, 16.3, 31.1),
+        1.217 * lobe(437.0, 11.8, 36.0) + 0.681 * lobe(459.0, 26.0, 13.8),
+    ]
+}
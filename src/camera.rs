@@ -1,36 +1,303 @@
 use std::f32::consts;
 
+use serde::Deserialize;
+use winit::{
+    event::{ElementState, KeyEvent, MouseButton, MouseScrollDelta, WindowEvent},
+    keyboard::{KeyCode, PhysicalKey},
+};
+
+use crate::uniforms::CameraData;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CameraMode {
+    /// Orbit around `target`, driven by mouse drags and the scroll wheel.
+    #[default]
+    Orbit,
+    /// Fly through space from `position` with WASD/QE and mouse-look.
+    FreeFly,
+}
+
 pub struct Camera {
     pub screen_size: (u32, u32),
+    pub mode: CameraMode,
+    /// Point the camera orbits around and looks at.
+    pub target: glam::Vec3,
+    /// Where the camera is while flying freely.
+    pub position: glam::Vec3,
+    /// Free-fly speed in units per second.
+    pub fly_speed: f32,
+    /// Distance from the target.
     pub dist: f32,
-    pub angle: f32,
+    /// Horizontal angle around the target, in radians.
+    pub yaw: f32,
+    /// Angle above the target's horizontal plane, in radians.
+    pub pitch: f32,
+    /// Keep orbiting after the mouse button is released.
+    pub inertia: bool,
+    /// How quickly the orbit slows down with inertia, as a rate per second.
+    pub damping: f32,
+    /// Angular velocity of (yaw, pitch) in radians per second.
+    velocity: glam::Vec2,
+    /// Rotation applied by mouse drags since the last update.
+    drag_delta: glam::Vec2,
+    cursor: Option<glam::Vec2>,
+    rotating: bool,
+    panning: bool,
+    keys: FlyKeys,
+}
+
+/// Movement keys currently held down in free-fly mode.
+#[derive(Default)]
+struct FlyKeys {
+    forward: bool,
+    back: bool,
+    left: bool,
+    right: bool,
+    up: bool,
+    down: bool,
+    boost: bool,
 }
 
 const MODEL_CENTER_Y: f32 = 2.0;
+const FOV_Y: f32 = consts::FRAC_PI_4;
+
+// Stay clear of the poles, where the view would flip over.
+const MAX_PITCH: f32 = consts::FRAC_PI_2 - 0.05;
+const MIN_DIST: f32 = 5.0;
+const MAX_DIST: f32 = 350.0;
+// Radians per screen width or height dragged.
+const ROTATE_SPEED: f32 = 5.0;
+// Fraction of the distance zoomed per scroll line.
+const ZOOM_STEP: f32 = 0.1;
+// Pixels per scroll line, for touchpads reporting pixel deltas.
+const PIXELS_PER_LINE: f32 = 50.0;
+
+// Radians per pixel of raw mouse motion in free-fly mode.
+const LOOK_SENSITIVITY: f32 = 0.0025;
+// Speed multiplier while shift is held.
+const BOOST_FACTOR: f32 = 4.0;
+const MODE_TOGGLE_KEY: KeyCode = KeyCode::Tab;
+
+pub const DEFAULT_DAMPING: f32 = 4.0;
+pub const DEFAULT_FLY_SPEED: f32 = 30.0;
 
 impl Camera {
-    pub fn to_uniform_data(&self) -> [f32; 16 * 3 + 4] {
-        // aspect ratio
-        let aspect = self.screen_size.0 as f32 / self.screen_size.1 as f32;
-        let proj = glam::Mat4::perspective_rh(consts::FRAC_PI_4, aspect, 1.0, 400.0);
-
-        let eye = glam::Vec3::new(
-            self.dist * self.angle.cos(),
-            self.dist * 0.5,
-            self.dist * self.angle.sin(),
-        );
-        let center = glam::Vec3::new(0.0, MODEL_CENTER_Y, 0.0);
+    pub fn new(screen_size: (u32, u32), dist: f32) -> Self {
+        Self {
+            screen_size,
+            mode: CameraMode::Orbit,
+            target: glam::Vec3::new(0.0, MODEL_CENTER_Y, 0.0),
+            position: glam::Vec3::ZERO,
+            fly_speed: DEFAULT_FLY_SPEED,
+            dist: dist.clamp(MIN_DIST, MAX_DIST),
+            yaw: -2.5,
+            pitch: 0.5f32.atan(),
+            inertia: false,
+            damping: DEFAULT_DAMPING,
+            velocity: glam::Vec2::ZERO,
+            drag_delta: glam::Vec2::ZERO,
+            cursor: None,
+            rotating: false,
+            panning: false,
+            keys: FlyKeys::default(),
+        }
+    }
+
+    /// Unit vector pointing from the orbit target to the camera.
+    fn orbit_direction(&self) -> glam::Vec3 {
+        let (sin_yaw, cos_yaw) = self.yaw.sin_cos();
+        let (sin_pitch, cos_pitch) = self.pitch.sin_cos();
+        glam::Vec3::new(cos_pitch * cos_yaw, sin_pitch, cos_pitch * sin_yaw)
+    }
+
+    /// Direction the camera looks in. Both modes share yaw and pitch, so switching keeps the view.
+    pub fn forward(&self) -> glam::Vec3 {
+        -self.orbit_direction()
+    }
+
+    pub fn eye(&self) -> glam::Vec3 {
+        match self.mode {
+            CameraMode::Orbit => self.target + self.dist * self.orbit_direction(),
+            CameraMode::FreeFly => self.position,
+        }
+    }
+
+    fn look_at(&self) -> glam::Vec3 {
+        match self.mode {
+            CameraMode::Orbit => self.target,
+            CameraMode::FreeFly => self.position + self.forward(),
+        }
+    }
+
+    /// Switch between orbit and free-fly, starting the new mode from the current view.
+    pub fn toggle_mode(&mut self) {
+        self.mode = match self.mode {
+            CameraMode::Orbit => {
+                self.position = self.eye();
+                CameraMode::FreeFly
+            }
+            CameraMode::FreeFly => {
+                self.target = self.position + self.forward() * self.dist;
+                CameraMode::Orbit
+            }
+        };
+        self.velocity = glam::Vec2::ZERO;
+        self.rotating = false;
+        self.panning = false;
+        log::info!("Camera mode {:?}", self.mode);
+    }
+
+    fn aspect(&self) -> f32 {
+        self.screen_size.0 as f32 / self.screen_size.1.max(1) as f32
+    }
+
+    /// Rotate with the left mouse button, pan with the right one and zoom with the wheel.
+    /// In free-fly mode, move with WASD/QE and boost with shift.
+    pub fn process_event(&mut self, event: &WindowEvent) {
+        match event {
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        physical_key: PhysicalKey::Code(code),
+                        state,
+                        repeat,
+                        ..
+                    },
+                ..
+            } => {
+                let pressed = *state == ElementState::Pressed;
+                match code {
+                    &MODE_TOGGLE_KEY if pressed && !repeat => self.toggle_mode(),
+                    KeyCode::KeyW => self.keys.forward = pressed,
+                    KeyCode::KeyS => self.keys.back = pressed,
+                    KeyCode::KeyA => self.keys.left = pressed,
+                    KeyCode::KeyD => self.keys.right = pressed,
+                    KeyCode::KeyE => self.keys.up = pressed,
+                    KeyCode::KeyQ => self.keys.down = pressed,
+                    KeyCode::ShiftLeft | KeyCode::ShiftRight => self.keys.boost = pressed,
+                    _ => {}
+                }
+            }
+            WindowEvent::MouseInput { state, button, .. } if self.mode == CameraMode::Orbit => {
+                let pressed = *state == ElementState::Pressed;
+                match button {
+                    MouseButton::Left => self.rotating = pressed,
+                    MouseButton::Right => self.panning = pressed,
+                    _ => {}
+                }
+            }
+            WindowEvent::CursorMoved { position, .. } => {
+                let cursor = glam::Vec2::new(
+                    position.x as f32 / self.screen_size.0.max(1) as f32 - 0.5,
+                    position.y as f32 / self.screen_size.1.max(1) as f32 - 0.5,
+                );
+                if let Some(last) = self.cursor.replace(cursor) {
+                    let delta = cursor - last;
+                    if self.rotating {
+                        let rotation = delta * ROTATE_SPEED;
+                        self.rotate(rotation);
+                        self.drag_delta += rotation;
+                    }
+                    if self.panning {
+                        self.pan(delta);
+                    }
+                }
+            }
+            WindowEvent::CursorLeft { .. } => {
+                self.cursor = None;
+            }
+            WindowEvent::MouseWheel { delta, .. } if self.mode == CameraMode::Orbit => {
+                let lines = match delta {
+                    MouseScrollDelta::LineDelta(_, y) => *y,
+                    MouseScrollDelta::PixelDelta(position) => position.y as f32 / PIXELS_PER_LINE,
+                };
+                self.dist = (self.dist * (1.0 - ZOOM_STEP).powf(lines)).clamp(MIN_DIST, MAX_DIST);
+            }
+            _ => {}
+        }
+    }
+
+    /// Mouse-look from raw mouse motion, which keeps coming while the cursor is grabbed.
+    pub fn process_mouse_motion(&mut self, delta: (f64, f64)) {
+        if self.mode == CameraMode::FreeFly {
+            self.rotate(glam::Vec2::new(delta.0 as f32, delta.1 as f32) * LOOK_SENSITIVITY);
+        }
+    }
+
+    /// Advance inertia and free-fly movement by `dt` seconds.
+    pub fn update(&mut self, dt: f32) {
+        if self.mode == CameraMode::FreeFly {
+            self.fly(dt);
+        } else if self.rotating {
+            // Remember how fast the user was dragging, so a release keeps that speed.
+            if dt > 0.0 {
+                self.velocity = self.drag_delta / dt;
+            }
+        } else if self.inertia {
+            self.rotate(self.velocity * dt);
+            self.velocity *= (-self.damping * dt).exp();
+        } else {
+            self.velocity = glam::Vec2::ZERO;
+        }
+        self.drag_delta = glam::Vec2::ZERO;
+    }
+
+    /// Move the camera along with something that moved by `offset`, in either mode.
+    pub fn follow(&mut self, offset: glam::Vec3) {
+        self.target += offset;
+        self.position += offset;
+    }
+
+    fn fly(&mut self, dt: f32) {
+        let axis = |positive: bool, negative: bool| positive as i32 as f32 - negative as i32 as f32;
+
+        let forward = self.forward();
+        let right = forward.cross(glam::Vec3::Y).normalize();
+        let direction = forward * axis(self.keys.forward, self.keys.back)
+            + right * axis(self.keys.right, self.keys.left)
+            + glam::Vec3::Y * axis(self.keys.up, self.keys.down);
+
+        let speed = if self.keys.boost {
+            self.fly_speed * BOOST_FACTOR
+        } else {
+            self.fly_speed
+        };
+        self.position += direction.normalize_or_zero() * speed * dt;
+    }
+
+    fn rotate(&mut self, delta: glam::Vec2) {
+        self.yaw += delta.x;
+        self.pitch = (self.pitch + delta.y).clamp(-MAX_PITCH, MAX_PITCH);
+    }
+
+    /// Move the target so the point under the cursor follows it, `delta` is in screen units.
+    fn pan(&mut self, delta: glam::Vec2) {
+        let forward = self.forward();
+        let right = forward.cross(glam::Vec3::Y).normalize();
+        let up = right.cross(forward);
+
+        let view_height = 2.0 * self.dist * (FOV_Y * 0.5).tan();
+        let view_width = view_height * self.aspect();
+        self.target += up * delta.y * view_height - right * delta.x * view_width;
+    }
+
+    pub fn to_uniform_data(&self) -> CameraData {
+        let proj = glam::Mat4::perspective_rh(FOV_Y, self.aspect(), 1.0, 400.0);
+
+        let eye = self.eye();
         let up = glam::Vec3::Y;
 
-        let view = glam::Mat4::look_at_rh(eye, center, up);
+        let view = glam::Mat4::look_at_rh(eye, self.look_at(), up);
         let proj_inv = proj.inverse();
 
-        let mut raw = [0f32; 16 * 3 + 4];
-        raw[..16].copy_from_slice(&AsRef::<[f32; 16]>::as_ref(&proj)[..]);
-        raw[16..32].copy_from_slice(&AsRef::<[f32; 16]>::as_ref(&proj_inv)[..]);
-        raw[32..48].copy_from_slice(&AsRef::<[f32; 16]>::as_ref(&view)[..]);
-        raw[48..51].copy_from_slice(&eye.to_array()[..]);
-        raw[51] = 1.0;
-        raw
+        CameraData {
+            proj: proj.to_cols_array_2d(),
+            proj_inv: proj_inv.to_cols_array_2d(),
+            view: view.to_cols_array_2d(),
+            position: eye.extend(1.0).to_array(),
+            screen_size: [self.screen_size.0 as f32, self.screen_size.1 as f32],
+            _padding: [0.0; 2],
+        }
     }
 }
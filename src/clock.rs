@@ -0,0 +1,168 @@
+use crate::Cli;
+
+// Longer frames, e.g. while the window is being dragged, would make the simulation jump.
+const MAX_FRAME_TIME: f32 = 0.25;
+// Step of headless captures without --fixed-step, so they come out the same on fast and slow
+// machines, and of single steps in real-time mode.
+const DEFAULT_STEP: f32 = 1.0 / 60.0;
+// Factor the time scale changes by with each speed up or slow down.
+const TIME_SCALE_FACTOR: f32 = 2.0;
+// Bounds of the time scale's magnitude. Halving without a floor would underflow to zero, which
+// doubling can't recover from.
+const MIN_TIME_SCALE: f32 = 1.0 / 1024.0;
+const MAX_TIME_SCALE: f32 = 1024.0;
+
+/// How long a frame lasts for the simulation.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ClockMode {
+    /// As long as it took on the wall clock.
+    RealTime,
+    /// Whole steps of this many seconds, as many as fit into the wall clock time so far. Frames
+    /// may advance by none or several, but the simulation keeps pace with real time.
+    FixedStep(f32),
+    /// Exactly one step of this many seconds per frame, however long the frame took. For headless
+    /// captures, which shouldn't depend on how fast the machine is.
+    PerFrame(f32),
+}
+
+/// Time that passed since the previous frame, handed to [`Framework::render`](crate::framework::Framework::render).
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct FrameTime {
+    /// Seconds the frame lasted, for input and the camera. Pausing and the time scale don't apply.
+    pub real: f32,
+    /// Simulated seconds, after the time scale. Zero while paused, negative when running backwards.
+    pub sim: f32,
+    /// Simulated seconds since the start.
+    pub sim_time: f64,
+}
+
+/// Simulation clock that animations are driven by, so they run at the same speed at any frame rate.
+pub struct Clock {
+    pub mode: ClockMode,
+    /// Simulated seconds per real second, negative values run the simulation backwards.
+    pub time_scale: f32,
+    pub paused: bool,
+    sim_time: f64,
+    last_tick: Option<web_time::Instant>,
+    // Real seconds not yet spent on a whole fixed step.
+    accumulator: f32,
+    // Advance the next frame despite being paused.
+    pending_step: bool,
+}
+
+impl Clock {
+    pub fn new(mode: ClockMode, time_scale: f32) -> Self {
+        Self {
+            mode,
+            time_scale: clamp_time_scale(time_scale),
+            paused: false,
+            sim_time: 0.0,
+            last_tick: None,
+            accumulator: 0.0,
+            pending_step: false,
+        }
+    }
+
+    /// The clock asked for on the command line. Headless rendering advances one step per frame.
+    pub fn from_args(args: &Cli) -> Self {
+        let mode = match args.fixed_step {
+            _ if args.headless => ClockMode::PerFrame(args.fixed_step.unwrap_or(DEFAULT_STEP)),
+            Some(step) => ClockMode::FixedStep(step),
+            None => ClockMode::RealTime,
+        };
+        let mut clock = Self::new(mode, args.time_scale);
+        clock.paused = args.paused;
+        clock
+    }
+
+    pub fn sim_time(&self) -> f64 {
+        self.sim_time
+    }
+
+    pub fn toggle_pause(&mut self) {
+        self.paused = !self.paused;
+    }
+
+    /// Pause, and advance the next frame by a single step.
+    pub fn step(&mut self) {
+        self.paused = true;
+        self.pending_step = true;
+    }
+
+    pub fn speed_up(&mut self) {
+        self.time_scale = clamp_time_scale(self.time_scale * TIME_SCALE_FACTOR);
+    }
+
+    pub fn slow_down(&mut self) {
+        self.time_scale = clamp_time_scale(self.time_scale / TIME_SCALE_FACTOR);
+    }
+
+    /// Run the simulation the other way.
+    pub fn reverse(&mut self) {
+        self.time_scale = -self.time_scale;
+    }
+
+    /// Start the next frame. The first one lasts no time unless stepping per frame.
+    pub fn tick(&mut self) -> FrameTime {
+        self.tick_at(web_time::Instant::now())
+    }
+
+    /// Start the next frame at `now`.
+    pub fn tick_at(&mut self, now: web_time::Instant) -> FrameTime {
+        let real = match self.mode {
+            ClockMode::PerFrame(step) => step,
+            ClockMode::RealTime | ClockMode::FixedStep(_) => self
+                .last_tick
+                .map_or(0.0, |last_tick| (now - last_tick).as_secs_f32())
+                .min(MAX_FRAME_TIME),
+        };
+        self.last_tick = Some(now);
+
+        let step = match self.mode {
+            ClockMode::RealTime => DEFAULT_STEP,
+            ClockMode::FixedStep(step) | ClockMode::PerFrame(step) => step,
+        };
+        let duration = if !self.paused {
+            match self.mode {
+                ClockMode::FixedStep(step) => {
+                    self.accumulator += real;
+                    let steps = (self.accumulator / step).floor();
+                    self.accumulator -= steps * step;
+                    steps * step
+                }
+                ClockMode::RealTime | ClockMode::PerFrame(_) => real,
+            }
+        } else if std::mem::take(&mut self.pending_step) {
+            step
+        } else {
+            0.0
+        };
+        if self.paused {
+            // Time spent paused doesn't pile up into a burst of steps on resume
+            self.accumulator = 0.0;
+        }
+        let sim = duration * self.time_scale;
+        self.sim_time += sim as f64;
+
+        FrameTime {
+            real,
+            sim,
+            sim_time: self.sim_time,
+        }
+    }
+}
+
+/// Keep the sign, but bring the magnitude within [`MIN_TIME_SCALE`, `MAX_TIME_SCALE`].
+fn clamp_time_scale(time_scale: f32) -> f32 {
+    time_scale.signum() * time_scale.abs().clamp(MIN_TIME_SCALE, MAX_TIME_SCALE)
+}
+
+impl std::fmt::Display for Clock {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "t = {:.1} s, {}x", self.sim_time, self.time_scale)?;
+        if self.paused {
+            write!(f, ", paused")?;
+        }
+        Ok(())
+    }
+}
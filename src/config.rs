@@ -0,0 +1,946 @@
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use crate::{
+    blackbody,
+    camera::{Camera, CameraMode},
+    nbody::{Integrator, Simulation},
+    orbit::KeplerOrbit,
+    uniforms::{BlackHoleData, LightingData},
+    Cli, Material, SphereMesh, Transform,
+};
+
+const DEFAULT_CAMERA_DISTANCE: f32 = 150.0;
+// Off to the side, so the sphere doesn't hide the black hole at the origin.
+const SPHERE_POSITION: [f32; 3] = [70.0, 2.0, 0.0];
+// Seconds per turn of the sphere and models, 0.018 radians per second.
+const DEFAULT_ROTATION_PERIOD: f64 = std::f64::consts::TAU / 0.018;
+// Each one quadruples the triangles, 8 already makes over a million.
+const MAX_SUBDIVISIONS: u32 = 8;
+// Twelve triangles per grid cell, 256 makes about as many as the finest icosphere.
+const MAX_RESOLUTION: u32 = 256;
+
+/// Everything `Scene::init` builds the scene from.
+///
+/// It is either read from a TOML scene file with `--scene`, or assembled from
+/// the other command line arguments by [`Config::from_cli`].
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    #[serde(default)]
+    pub camera: CameraConfig,
+    /// KTX2 file or skybox directory, relative to the scene file.
+    #[serde(default)]
+    pub skybox: Option<PathBuf>,
+    #[serde(default)]
+    pub black_hole: BlackHoleConfig,
+    #[serde(default)]
+    pub lighting: LightingConfig,
+    #[serde(default)]
+    pub simulation: SimulationConfig,
+    #[serde(default)]
+    pub entities: Vec<EntityConfig>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CameraConfig {
+    #[serde(default)]
+    pub mode: CameraMode,
+    /// Distance from the orbit target.
+    #[serde(default = "default_camera_distance")]
+    pub distance: f32,
+    /// Horizontal orbit angle in degrees.
+    pub yaw: Option<f32>,
+    /// Orbit angle above the target in degrees.
+    pub pitch: Option<f32>,
+    pub target: Option<[f32; 3]>,
+    /// Start position in free-fly mode, defaults to where the orbit would put the camera.
+    pub position: Option<[f32; 3]>,
+    #[serde(default)]
+    pub inertia: bool,
+    pub damping: Option<f32>,
+    pub fly_speed: Option<f32>,
+    /// Name of an entity the camera moves along with. The orbit target starts on it.
+    pub attach: Option<String>,
+}
+
+impl Default for CameraConfig {
+    fn default() -> Self {
+        Self {
+            mode: CameraMode::default(),
+            distance: DEFAULT_CAMERA_DISTANCE,
+            yaw: None,
+            pitch: None,
+            target: None,
+            position: None,
+            inertia: false,
+            damping: None,
+            fly_speed: None,
+            attach: None,
+        }
+    }
+}
+
+impl CameraConfig {
+    /// A camera in the configured starting state, rendering to `screen_size` pixels.
+    pub fn create_camera(&self, screen_size: (u32, u32)) -> Camera {
+        let mut camera = Camera::new(screen_size, self.distance);
+        if let Some(yaw) = self.yaw {
+            camera.yaw = yaw.to_radians();
+        }
+        if let Some(pitch) = self.pitch {
+            camera.pitch = pitch.to_radians();
+        }
+        if let Some(target) = self.target {
+            camera.target = target.into();
+        }
+        camera.inertia = self.inertia;
+        if let Some(damping) = self.damping {
+            camera.damping = damping;
+        }
+        if let Some(fly_speed) = self.fly_speed {
+            camera.fly_speed = fly_speed;
+        }
+        if self.mode == CameraMode::FreeFly {
+            camera.toggle_mode();
+            if let Some(position) = self.position {
+                camera.position = position.into();
+            }
+        }
+        camera
+    }
+}
+
+fn default_camera_distance() -> f32 {
+    DEFAULT_CAMERA_DISTANCE
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Metric {
+    /// Non-rotating black hole.
+    #[default]
+    Schwarzschild,
+    /// Rotating black hole with `spin`, which drags light along with it.
+    Kerr,
+}
+
+impl Metric {
+    pub fn toggle(self) -> Self {
+        match self {
+            Metric::Schwarzschild => Metric::Kerr,
+            Metric::Kerr => Metric::Schwarzschild,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BlackHoleConfig {
+    pub metric: Metric,
+    /// Mass in scene units (G = c = 1), the event horizon is at twice this radius.
+    pub mass: f32,
+    /// Kerr spin parameter a in units of the mass, between -1 and 1. Only used by the Kerr metric.
+    pub spin: f32,
+    /// Tilt of the spin axis and the disk from the y axis towards z, in degrees.
+    pub inclination: f32,
+    /// Defaults to the innermost stable circular orbit, smaller values are raised to it.
+    pub disk_inner_radius: Option<f32>,
+    pub disk_outer_radius: f32,
+    /// Relative to the rate that heats the disk around a non-rotating hole to 5000 K.
+    /// The temperature goes with its fourth root.
+    pub accretion_rate: f32,
+    /// Integration steps per pixel before a ray counts as captured.
+    pub max_steps: u32,
+    /// Accepted integration error per step, relative to the distance from the hole.
+    pub tolerance: f32,
+}
+
+impl Default for BlackHoleConfig {
+    fn default() -> Self {
+        Self {
+            metric: Metric::default(),
+            mass: 2.5,
+            spin: 0.9,
+            inclination: 0.0,
+            disk_inner_radius: None,
+            disk_outer_radius: 40.0,
+            accretion_rate: 1.0,
+            max_steps: 300,
+            tolerance: 1e-4,
+        }
+    }
+}
+
+impl BlackHoleConfig {
+    /// Radius of the innermost stable circular orbit (Bardeen et al. 1972), for a disk that
+    /// orbits in the direction of positive spin.
+    pub fn isco_radius(&self) -> f32 {
+        let spin = match self.metric {
+            Metric::Schwarzschild => 0.0,
+            Metric::Kerr => self.spin.clamp(-1.0, 1.0),
+        };
+        let z1 = 1.0 + (1.0 - spin * spin).cbrt() * ((1.0 + spin).cbrt() + (1.0 - spin).cbrt());
+        let z2 = (3.0 * spin * spin + z1 * z1).sqrt();
+        let root = ((3.0 - z1) * (3.0 + z1 + 2.0 * z2)).max(0.0).sqrt();
+        self.mass * (3.0 + z2 - spin.signum() * root)
+    }
+
+    pub fn disk_inner_radius(&self) -> f32 {
+        let isco = self.isco_radius();
+        self.disk_inner_radius
+            .map_or(isco, |radius| radius.max(isco))
+    }
+
+    pub fn to_uniform_data(&self) -> BlackHoleData {
+        BlackHoleData {
+            mass: self.mass,
+            disk_inner_radius: self.disk_inner_radius(),
+            disk_outer_radius: self.disk_outer_radius,
+            tolerance: self.tolerance,
+            max_steps: self.max_steps as f32,
+            spin: self.spin,
+            inclination: self.inclination.to_radians(),
+            metric: match self.metric {
+                Metric::Schwarzschild => 0.0,
+                Metric::Kerr => 1.0,
+            },
+            accretion_rate: self.accretion_rate,
+            _padding: [0.0; 3],
+        }
+    }
+}
+
+/// How entities are lit, see `LightingData` in shader.wgsl.
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LightingConfig {
+    /// Direction towards the light, which is infinitely far away.
+    pub direction: [f32; 3],
+    /// Linear RGB color of the light.
+    pub color: [f32; 3],
+    /// Linear RGB light that reaches every surface, including the side facing away from the light.
+    pub ambient_color: [f32; 3],
+    pub specular_strength: f32,
+    /// Phong exponent, larger values give smaller highlights.
+    pub shininess: f32,
+    /// How much of the skybox reflection shows over the lit surface, between 0 and 1.
+    pub reflectivity: f32,
+}
+
+impl Default for LightingConfig {
+    fn default() -> Self {
+        Self {
+            direction: [1.0, 1.0, 1.0],
+            color: [1.0, 1.0, 0.9],
+            ambient_color: [0.02, 0.02, 0.03],
+            specular_strength: 0.8,
+            shininess: 64.0,
+            reflectivity: 0.7,
+        }
+    }
+}
+
+impl LightingConfig {
+    /// The direction is normalized for the shader.
+    pub fn to_uniform_data(&self) -> LightingData {
+        LightingData {
+            direction: glam::Vec3::from(self.direction)
+                .normalize_or_zero()
+                .to_array(),
+            shininess: self.shininess,
+            color: self.color,
+            specular_strength: self.specular_strength,
+            ambient_color: self.ambient_color,
+            reflectivity: self.reflectivity,
+        }
+    }
+}
+
+/// N-body gravity for the entities with a `body`.
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SimulationConfig {
+    pub integrator: Integrator,
+    /// Simulated seconds per integration step.
+    pub time_step: f64,
+    /// Plummer softening length, in scene units.
+    pub softening: f64,
+    /// G, which is 1 in the black hole's units. Larger values speed up orbits of the same shape.
+    pub gravitational_constant: f64,
+    /// Whether the black hole's mass pulls on the bodies.
+    pub black_hole_gravity: bool,
+}
+
+impl Default for SimulationConfig {
+    fn default() -> Self {
+        Self {
+            integrator: Integrator::default(),
+            time_step: 0.01,
+            softening: 0.1,
+            gravitational_constant: 1.0,
+            black_hole_gravity: true,
+        }
+    }
+}
+
+impl SimulationConfig {
+    /// A simulation without bodies, and the black hole in the middle unless it's turned off.
+    pub fn create_simulation(&self, black_hole: &BlackHoleConfig) -> Simulation {
+        let mut simulation = Simulation::new(self.integrator, self.time_step);
+        simulation.softening = self.softening;
+        simulation.gravitational_constant = self.gravitational_constant;
+        if self.black_hole_gravity {
+            simulation.central_mass = black_hole.mass as f64;
+        }
+        simulation
+    }
+}
+
+/// Mass and starting velocity of an entity moved by the N-body simulation.
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BodyConfig {
+    pub mass: f64,
+    #[serde(default)]
+    pub velocity: [f64; 3],
+}
+
+/// Keplerian orbit of an entity around its parent, or the black hole, see [`KeplerOrbit`].
+/// It lies in the parent's frame, so a tilted planet's moons circle its equator.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct OrbitConfig {
+    pub semi_major_axis: f64,
+    #[serde(default)]
+    pub eccentricity: f64,
+    /// Angles in degrees.
+    #[serde(default)]
+    pub inclination: f64,
+    #[serde(default)]
+    pub longitude_of_ascending_node: f64,
+    #[serde(default)]
+    pub argument_of_periapsis: f64,
+    #[serde(default)]
+    pub mean_anomaly_at_epoch: f64,
+    /// Seconds per lap. Follows from the mass of the black hole or the parent's body if unset.
+    pub period: Option<f64>,
+}
+
+/// Line through where the entity has been, and for bodies where it's heading.
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TrailConfig {
+    /// Simulated seconds of history, fading out towards the end. 0 for none.
+    pub duration: f64,
+    /// Simulated seconds the N-body simulation looks ahead for the predicted path. 0 for none.
+    pub prediction: f64,
+    /// In pixels.
+    pub width: f32,
+    /// Linear RGB.
+    pub color: [f32; 3],
+    pub opacity: f32,
+}
+
+impl Default for TrailConfig {
+    fn default() -> Self {
+        Self {
+            duration: 10.0,
+            prediction: 0.0,
+            width: 2.0,
+            color: [1.0; 3],
+            opacity: 0.8,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EntityConfig {
+    pub name: Option<String>,
+    /// Name of the entity this one is attached to. Position, rotation and orbit are relative to
+    /// the parent, its scale only applies to itself.
+    pub parent: Option<String>,
+    pub mesh: MeshConfig,
+    #[serde(default)]
+    pub position: [f32; 3],
+    /// Euler angles around x, y and z in degrees, applied in that order.
+    #[serde(default)]
+    pub rotation: [f32; 3],
+    /// Seconds per turn around the entity's own y axis, negative values turn the other way.
+    pub rotation_period: Option<f64>,
+    #[serde(default = "default_scale")]
+    pub scale: [f32; 3],
+    #[serde(default)]
+    pub material: MaterialConfig,
+    /// Moves the entity with gravity, starting from `position`. Not for entities with a parent.
+    pub body: Option<BodyConfig>,
+    /// Moves the entity along a fixed orbit instead, `position` is ignored.
+    pub orbit: Option<OrbitConfig>,
+    pub trail: Option<TrailConfig>,
+}
+
+fn default_scale() -> [f32; 3] {
+    [1.0; 3]
+}
+
+impl EntityConfig {
+    pub fn transform(&self) -> Transform {
+        let [x, y, z] = self.rotation.map(f32::to_radians);
+        Transform {
+            position: self.position.into(),
+            rotation: glam::Quat::from_euler(glam::EulerRot::XYZ, x, y, z),
+            scale: self.scale.into(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(try_from = "MeshFields")]
+pub enum MeshConfig {
+    /// UV sphere from `generate_sphere`, unset values use its defaults.
+    Sphere {
+        radius: Option<f32>,
+        stacks: Option<u32>,
+        slices: Option<u32>,
+    },
+    /// Sphere from `generate_icosphere`, evenly covered with triangles.
+    Icosphere {
+        radius: Option<f32>,
+        subdivisions: Option<u32>,
+    },
+    /// Sphere from `generate_cube_sphere`, evenly covered with quads.
+    CubeSphere {
+        radius: Option<f32>,
+        resolution: Option<u32>,
+    },
+    /// OBJ model, relative to the scene file.
+    Obj { path: PathBuf },
+}
+
+// How a [`MeshConfig`] is written in scene files, `type` picks the variant. A tagged enum would
+// buffer the table first, and errors would lose the key of the bad value.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MeshFields {
+    #[serde(rename = "type")]
+    kind: MeshKind,
+    radius: Option<f32>,
+    stacks: Option<u32>,
+    slices: Option<u32>,
+    subdivisions: Option<u32>,
+    resolution: Option<u32>,
+    path: Option<PathBuf>,
+}
+
+#[derive(Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum MeshKind {
+    Sphere,
+    Icosphere,
+    CubeSphere,
+    Obj,
+}
+
+impl TryFrom<MeshFields> for MeshConfig {
+    type Error = String;
+
+    fn try_from(fields: MeshFields) -> Result<Self, String> {
+        let MeshFields {
+            kind,
+            radius,
+            stacks,
+            slices,
+            subdivisions,
+            resolution,
+            path,
+        } = fields;
+        let (name, keys): (&str, &[&str]) = match kind {
+            MeshKind::Sphere => ("sphere", &["radius", "stacks", "slices"]),
+            MeshKind::Icosphere => ("icosphere", &["radius", "subdivisions"]),
+            MeshKind::CubeSphere => ("cube_sphere", &["radius", "resolution"]),
+            MeshKind::Obj => ("obj", &["path"]),
+        };
+        let set = [
+            ("radius", radius.is_some()),
+            ("stacks", stacks.is_some()),
+            ("slices", slices.is_some()),
+            ("subdivisions", subdivisions.is_some()),
+            ("resolution", resolution.is_some()),
+            ("path", path.is_some()),
+        ];
+        if let Some((key, _)) = set.iter().find(|(key, set)| *set && !keys.contains(key)) {
+            return Err(format!(
+                "`{}` doesn't apply to `{}` meshes, expected one of `{}`",
+                key,
+                name,
+                keys.join("`, `")
+            ));
+        }
+
+        Ok(match kind {
+            MeshKind::Sphere => MeshConfig::Sphere {
+                radius,
+                stacks,
+                slices,
+            },
+            MeshKind::Icosphere => MeshConfig::Icosphere {
+                radius,
+                subdivisions,
+            },
+            MeshKind::CubeSphere => MeshConfig::CubeSphere { radius, resolution },
+            MeshKind::Obj => MeshConfig::Obj {
+                path: path.ok_or("`obj` meshes need a `path`")?,
+            },
+        })
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MaterialConfig {
+    /// Linear RGB tint of the lit surface.
+    pub color: [f32; 3],
+    /// Also tint the surface with the color of a blackbody at this many kelvin, like a star.
+    pub temperature: Option<f32>,
+    /// sRGB image multiplied into the color, relative to the scene file.
+    pub albedo_map: Option<PathBuf>,
+    /// Tangent-space normal map, relative to the scene file.
+    pub normal_map: Option<PathBuf>,
+}
+
+impl MaterialConfig {
+    /// Whether the entity needs UVs and tangents, and a texture bind group.
+    pub fn is_textured(&self) -> bool {
+        self.albedo_map.is_some() || self.normal_map.is_some()
+    }
+}
+
+impl Default for MaterialConfig {
+    fn default() -> Self {
+        Self {
+            color: [1.0; 3],
+            temperature: None,
+            albedo_map: None,
+            normal_map: None,
+        }
+    }
+}
+
+impl From<&MaterialConfig> for Material {
+    fn from(config: &MaterialConfig) -> Self {
+        let tint = config
+            .temperature
+            .map_or([1.0; 3], blackbody::blackbody_rgb);
+        Material {
+            color: glam::Vec3::from(config.color) * glam::Vec3::from(tint),
+        }
+    }
+}
+
+impl Config {
+    /// Read a TOML scene file. Relative paths in it are resolved against the file's directory.
+    pub fn load(path: &Path) -> Result<Self, anyhow::Error> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", path.display(), e))?;
+        let mut config = Self::parse(&text)
+            .map_err(|e| e.context(format!("Invalid scene file {}", path.display())))?;
+
+        let base_dir = path.parent().unwrap_or(Path::new(""));
+        config.resolve_paths(base_dir);
+
+        Ok(config)
+    }
+
+    pub fn parse(text: &str) -> Result<Self, anyhow::Error> {
+        let config: Self = serde_path_to_error::deserialize(toml::Deserializer::new(text))
+            .map_err(|e| {
+                let key = e.path().to_string();
+                let error = e.into_inner();
+                let line = error
+                    .span()
+                    .map(|span| text[..span.start].matches('\n').count() + 1);
+                match (key.as_str(), line) {
+                    // Syntax errors aren't tied to a key, toml's own message points at the spot.
+                    (".", _) => anyhow::anyhow!("{}", error),
+                    (_, Some(line)) => {
+                        anyhow::anyhow!("`{}` (line {}): {}", key, line, error.message())
+                    }
+                    (_, None) => anyhow::anyhow!("`{}`: {}", key, error.message()),
+                }
+            })?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// The scene described by the individual command line arguments, checked like a scene file.
+    pub fn from_cli(args: &Cli) -> Result<Self, anyhow::Error> {
+        let mut entities = vec![EntityConfig {
+            name: Some("sphere".to_string()),
+            parent: None,
+            mesh: match args.sphere_mesh {
+                SphereMesh::Uv => MeshConfig::Sphere {
+                    radius: args.sphere_radius,
+                    stacks: args.sphere_stacks,
+                    slices: args.sphere_slices,
+                },
+                SphereMesh::Icosphere => MeshConfig::Icosphere {
+                    radius: args.sphere_radius,
+                    subdivisions: args.sphere_subdivisions,
+                },
+                SphereMesh::CubeSphere => MeshConfig::CubeSphere {
+                    radius: args.sphere_radius,
+                    resolution: args.sphere_resolution,
+                },
+            },
+            position: SPHERE_POSITION,
+            rotation: [0.0; 3],
+            rotation_period: Some(DEFAULT_ROTATION_PERIOD),
+            scale: default_scale(),
+            material: MaterialConfig::default(),
+            body: None,
+            orbit: None,
+            trail: None,
+        }];
+
+        for (i, path) in args.models.iter().enumerate() {
+            entities.push(EntityConfig {
+                name: None,
+                parent: None,
+                mesh: MeshConfig::Obj { path: path.clone() },
+                position: args
+                    .model_positions
+                    .get(i)
+                    .map(|p| p.to_array())
+                    .unwrap_or(SPHERE_POSITION),
+                rotation: [0.0; 3],
+                rotation_period: Some(DEFAULT_ROTATION_PERIOD),
+                scale: [args.model_scales.get(i).copied().unwrap_or(1.0); 3],
+                material: MaterialConfig::default(),
+                body: None,
+                orbit: None,
+                trail: None,
+            });
+        }
+
+        let config = Self {
+            camera: CameraConfig {
+                distance: args.camera_distance.unwrap_or(DEFAULT_CAMERA_DISTANCE),
+                inertia: args.camera_inertia,
+                damping: args.camera_damping,
+                ..CameraConfig::default()
+            },
+            skybox: args.skybox.clone(),
+            black_hole: BlackHoleConfig::default(),
+            lighting: LightingConfig::default(),
+            simulation: SimulationConfig::default(),
+            entities,
+        };
+        config
+            .validate()
+            .map_err(|e| e.context("Invalid command line arguments"))?;
+        Ok(config)
+    }
+
+    pub fn entity_index(&self, name: &str) -> Option<usize> {
+        self.entities
+            .iter()
+            .position(|entity| entity.name.as_deref() == Some(name))
+    }
+
+    /// Index of the entity that entity `index` is attached to.
+    pub fn parent_index(&self, index: usize) -> Option<usize> {
+        self.entity_index(self.entities[index].parent.as_deref()?)
+    }
+
+    /// The orbit of entity `index` in radians, with the period from Kepler's third law unless it
+    /// is set. `None` without an orbit, or if the period isn't set and the parent has no mass.
+    pub fn create_orbit(&self, index: usize) -> Option<KeplerOrbit> {
+        let entity = &self.entities[index];
+        let orbit = entity.orbit.as_ref()?;
+        let period = match orbit.period {
+            Some(period) => period,
+            None => {
+                let mass = match &entity.parent {
+                    Some(_) => self.entities[self.parent_index(index)?].body?.mass,
+                    None => self.black_hole.mass as f64,
+                };
+                if mass <= 0.0 {
+                    return None;
+                }
+                KeplerOrbit::period_around(
+                    orbit.semi_major_axis,
+                    self.simulation.gravitational_constant * mass,
+                )
+            }
+        };
+        Some(KeplerOrbit {
+            semi_major_axis: orbit.semi_major_axis,
+            eccentricity: orbit.eccentricity,
+            inclination: orbit.inclination.to_radians(),
+            longitude_of_ascending_node: orbit.longitude_of_ascending_node.to_radians(),
+            argument_of_periapsis: orbit.argument_of_periapsis.to_radians(),
+            mean_anomaly_at_epoch: orbit.mean_anomaly_at_epoch.to_radians(),
+            period,
+        })
+    }
+
+    fn resolve_paths(&mut self, base_dir: &Path) {
+        if let Some(skybox) = self.skybox.as_mut() {
+            *skybox = base_dir.join(&*skybox);
+        }
+        for entity in self.entities.iter_mut() {
+            if let MeshConfig::Obj { path } = &mut entity.mesh {
+                *path = base_dir.join(&*path);
+            }
+            let material = &mut entity.material;
+            for path in [&mut material.albedo_map, &mut material.normal_map]
+                .into_iter()
+                .flatten()
+            {
+                *path = base_dir.join(&*path);
+            }
+        }
+    }
+
+    /// Catch values that would deserialize fine but can't be rendered.
+    fn validate(&self) -> Result<(), anyhow::Error> {
+        fn check(ok: bool, key: &str, message: &str) -> Result<(), anyhow::Error> {
+            if ok {
+                Ok(())
+            } else {
+                Err(anyhow::anyhow!("`{}`: {}", key, message))
+            }
+        }
+
+        check(
+            self.camera.distance > 0.0,
+            "camera.distance",
+            "must be positive",
+        )?;
+        if let Some(attach) = &self.camera.attach {
+            check(
+                self.entity_index(attach).is_some(),
+                "camera.attach",
+                &format!("no entity is named {:?}", attach),
+            )?;
+        }
+
+        let black_hole = &self.black_hole;
+        check(
+            black_hole.mass >= 0.0,
+            "black_hole.mass",
+            "must not be negative",
+        )?;
+        check(
+            (-1.0..=1.0).contains(&black_hole.spin),
+            "black_hole.spin",
+            "must be between -1 and 1",
+        )?;
+        check(
+            black_hole.max_steps > 0,
+            "black_hole.max_steps",
+            "must be positive",
+        )?;
+        check(
+            black_hole.tolerance > 0.0,
+            "black_hole.tolerance",
+            "must be positive",
+        )?;
+        // The metric can be switched while running, which moves the ISCO
+        for metric in [Metric::Schwarzschild, Metric::Kerr] {
+            let inner_radius = BlackHoleConfig {
+                metric,
+                ..*black_hole
+            }
+            .disk_inner_radius();
+            check(
+                inner_radius < black_hole.disk_outer_radius,
+                "black_hole.disk_outer_radius",
+                &format!(
+                    "must be larger than the disk's inner radius, {} with the {:?} metric",
+                    inner_radius, metric
+                ),
+            )?;
+        }
+        check(
+            black_hole.accretion_rate >= 0.0,
+            "black_hole.accretion_rate",
+            "must not be negative",
+        )?;
+
+        let lighting = &self.lighting;
+        check(
+            glam::Vec3::from(lighting.direction) != glam::Vec3::ZERO,
+            "lighting.direction",
+            "must not be zero",
+        )?;
+        check(
+            lighting.shininess > 0.0,
+            "lighting.shininess",
+            "must be positive",
+        )?;
+        check(
+            (0.0..=1.0).contains(&lighting.reflectivity),
+            "lighting.reflectivity",
+            "must be between 0 and 1",
+        )?;
+
+        let simulation = &self.simulation;
+        check(
+            simulation.time_step > 0.0,
+            "simulation.time_step",
+            "must be positive",
+        )?;
+        check(
+            simulation.softening >= 0.0,
+            "simulation.softening",
+            "must not be negative",
+        )?;
+
+        for (i, entity) in self.entities.iter().enumerate() {
+            let radius = match entity.mesh {
+                MeshConfig::Sphere {
+                    radius,
+                    stacks,
+                    slices,
+                } => {
+                    check(
+                        stacks.is_none_or(|s| s >= 2),
+                        &format!("entities[{}].mesh.stacks", i),
+                        "must be at least 2",
+                    )?;
+                    check(
+                        slices.is_none_or(|s| s >= 3),
+                        &format!("entities[{}].mesh.slices", i),
+                        "must be at least 3",
+                    )?;
+                    radius
+                }
+                MeshConfig::Icosphere {
+                    radius,
+                    subdivisions,
+                } => {
+                    check(
+                        subdivisions.is_none_or(|s| s <= MAX_SUBDIVISIONS),
+                        &format!("entities[{}].mesh.subdivisions", i),
+                        &format!("must be at most {}", MAX_SUBDIVISIONS),
+                    )?;
+                    radius
+                }
+                MeshConfig::CubeSphere { radius, resolution } => {
+                    check(
+                        resolution.is_none_or(|r| (1..=MAX_RESOLUTION).contains(&r)),
+                        &format!("entities[{}].mesh.resolution", i),
+                        &format!("must be between 1 and {}", MAX_RESOLUTION),
+                    )?;
+                    radius
+                }
+                MeshConfig::Obj { .. } => None,
+            };
+            check(
+                radius.is_none_or(|r| r > 0.0),
+                &format!("entities[{}].mesh.radius", i),
+                "must be positive",
+            )?;
+            check(
+                entity.material.temperature.is_none_or(|t| t > 0.0),
+                &format!("entities[{}].material.temperature", i),
+                "must be positive",
+            )?;
+            check(
+                entity.scale.iter().all(|s| *s != 0.0),
+                &format!("entities[{}].scale", i),
+                "must not be zero",
+            )?;
+            check(
+                entity.rotation_period != Some(0.0),
+                &format!("entities[{}].rotation_period", i),
+                "must not be zero",
+            )?;
+            check(
+                entity.body.is_none_or(|body| body.mass >= 0.0),
+                &format!("entities[{}].body.mass", i),
+                "must not be negative",
+            )?;
+            if let Some(parent) = &entity.parent {
+                let key = format!("entities[{}].parent", i);
+                check(
+                    self.entity_index(parent).is_some(),
+                    &key,
+                    &format!("no entity is named {:?}", parent),
+                )?;
+                check(
+                    entity.body.is_none(),
+                    &key,
+                    "can't be combined with `body`, which moves in world space",
+                )?;
+                // Following the parents has to end at the top of the hierarchy
+                let mut ancestor = i;
+                for _ in 0..self.entities.len() {
+                    match self.parent_index(ancestor) {
+                        Some(index) => ancestor = index,
+                        None => break,
+                    }
+                    check(ancestor != i, &key, "must not lead back to this entity")?;
+                }
+            }
+            if let Some(trail) = &entity.trail {
+                let key = |field: &str| format!("entities[{}].trail.{}", i, field);
+                check(
+                    trail.duration >= 0.0,
+                    &key("duration"),
+                    "must not be negative",
+                )?;
+                check(
+                    trail.prediction >= 0.0,
+                    &key("prediction"),
+                    "must not be negative",
+                )?;
+                check(
+                    trail.prediction == 0.0 || entity.body.is_some(),
+                    &key("prediction"),
+                    "needs a `body` to predict",
+                )?;
+                check(trail.width > 0.0, &key("width"), "must be positive")?;
+                check(
+                    (0.0..=1.0).contains(&trail.opacity),
+                    &key("opacity"),
+                    "must be between 0 and 1",
+                )?;
+            }
+            if let Some(orbit) = &entity.orbit {
+                let key = |field: &str| format!("entities[{}].orbit.{}", i, field);
+                check(
+                    entity.body.is_none(),
+                    &format!("entities[{}].orbit", i),
+                    "can't be combined with `body`",
+                )?;
+                check(
+                    orbit.semi_major_axis > 0.0,
+                    &key("semi_major_axis"),
+                    "must be positive",
+                )?;
+                check(
+                    (0.0..1.0).contains(&orbit.eccentricity),
+                    &key("eccentricity"),
+                    "must be at least 0 and below 1",
+                )?;
+                check(
+                    orbit.period.is_none_or(|period| period > 0.0),
+                    &key("period"),
+                    "must be positive",
+                )?;
+                check(
+                    self.create_orbit(i).is_some(),
+                    &key("period"),
+                    "must be set when the parent has no mass",
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -3,13 +3,16 @@ use std::sync::Arc;
 use wgpu::{Instance, Surface};
 use winit::{
     dpi::PhysicalSize,
-    event::{Event, KeyEvent, StartCause, WindowEvent},
+    event::{DeviceEvent, ElementState, Event, KeyEvent, StartCause, WindowEvent},
     event_loop::{EventLoop, EventLoopWindowTarget},
     keyboard::{Key, NamedKey},
-    window::Window,
+    window::{CursorGrabMode, Window},
 };
 
-use crate::Cli;
+use crate::{
+    clock::{Clock, FrameTime},
+    Cli,
+};
 
 pub trait Framework: 'static + Sized {
     const SRGB: bool = true;
@@ -50,9 +53,26 @@ pub trait Framework: 'static + Sized {
         queue: &wgpu::Queue,
     );
 
+    /// Handle an input event. There's no frame time here: events only change state, like which
+    /// keys are held, and anything moving over time advances in [`Framework::render`].
     fn update(&mut self, event: WindowEvent);
 
-    fn render(&mut self, view: &wgpu::TextureView, device: &wgpu::Device, queue: &wgpu::Queue);
+    /// Raw mouse motion, which keeps arriving while the cursor is grabbed.
+    fn mouse_motion(&mut self, _delta: (f64, f64)) {}
+
+    /// Whether the cursor should be grabbed and hidden, e.g. for mouse-look.
+    fn cursor_grabbed(&self) -> bool {
+        false
+    }
+
+    /// Draw the next frame, with everything advanced by `time`.
+    fn render(
+        &mut self,
+        view: &wgpu::TextureView,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        time: FrameTime,
+    );
 }
 
 // Initialize logging in platform dependant ways.
@@ -80,15 +100,16 @@ fn init_logger() {
             std::panic::set_hook(Box::new(console_error_panic_hook::hook));
         } else {
             // parse_default_env will read the RUST_LOG environment variable and apply it on top
-            // of these default filters.
-            env_logger::builder()
+            // of these default filters. Tests render several times in one process, only the first
+            // call installs the logger.
+            let _ = env_logger::builder()
                 .filter_level(log::LevelFilter::Info)
                 // We keep wgpu at Error level, as it's very noisy.
                 .filter_module("wgpu_core", log::LevelFilter::Info)
                 .filter_module("wgpu_hal", log::LevelFilter::Error)
                 .filter_module("naga", log::LevelFilter::Error)
                 .parse_default_env()
-                .init();
+                .try_init();
         }
     }
 }
@@ -247,7 +268,7 @@ impl SurfaceWrapper {
         }
     }
 
-    pub fn get(&self) -> Option<&Surface> {
+    pub fn get(&self) -> Option<&Surface<'_>> {
         self.surface.as_ref()
     }
 
@@ -276,21 +297,53 @@ impl Context {
     ) -> Self {
         log::info!("Initializing wgpu...");
 
+        let instance = Self::create_instance();
+        surface.pre_adapter(&instance, window);
+        let adapter = wgpu::util::initialize_adapter_from_env_or_default(&instance, surface.get())
+            .await
+            .expect("No suitable GPU adapters found on the system!");
+
+        Self::init_device::<F>(instance, adapter).await
+    }
+
+    /// Initialize wgpu without a window or surface, for offscreen rendering.
+    ///
+    /// If no hardware adapter is available, we fall back to a software adapter
+    /// (llvmpipe, lavapipe, WARP) so this also works on GPU-less machines.
+    pub async fn init_headless_async<F: Framework>() -> Self {
+        log::info!("Initializing wgpu (headless)...");
+
+        let instance = Self::create_instance();
+        let adapter =
+            match wgpu::util::initialize_adapter_from_env_or_default(&instance, None).await {
+                Some(adapter) => adapter,
+                None => instance
+                    .request_adapter(&wgpu::RequestAdapterOptions {
+                        power_preference: wgpu::PowerPreference::default(),
+                        force_fallback_adapter: true,
+                        compatible_surface: None,
+                    })
+                    .await
+                    .expect("No suitable GPU or software adapters found on the system!"),
+            };
+
+        Self::init_device::<F>(instance, adapter).await
+    }
+
+    fn create_instance() -> wgpu::Instance {
         let backends = wgpu::util::backend_bits_from_env().unwrap_or_default();
         let dx12_shader_compiler = wgpu::util::dx12_shader_compiler_from_env().unwrap_or_default();
         let gles_minor_version = wgpu::util::gles_minor_version_from_env().unwrap_or_default();
 
-        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
+        wgpu::Instance::new(wgpu::InstanceDescriptor {
             backends,
             flags: wgpu::InstanceFlags::from_build_config().with_env(),
             dx12_shader_compiler,
             gles_minor_version,
-        });
-        surface.pre_adapter(&instance, window);
-        let adapter = wgpu::util::initialize_adapter_from_env_or_default(&instance, surface.get())
-            .await
-            .expect("No suitable GPU adapters found on the system!");
+        })
+    }
 
+    async fn init_device<F: Framework>(instance: wgpu::Instance, adapter: wgpu::Adapter) -> Self {
         let adapter_info = adapter.get_info();
         log::info!("Using {} ({:?})", adapter_info.name, adapter_info.backend);
 
@@ -381,6 +434,44 @@ impl Default for FrameCounter {
     }
 }
 
+/// Grab and hide the cursor, or release it again.
+fn set_cursor_grab(window: &Window, grab: bool) {
+    let result = if grab {
+        // Not every platform can lock the cursor in place, confining it to the window is close enough.
+        window
+            .set_cursor_grab(CursorGrabMode::Locked)
+            .or_else(|_| window.set_cursor_grab(CursorGrabMode::Confined))
+    } else {
+        window.set_cursor_grab(CursorGrabMode::None)
+    };
+    if let Err(e) = result {
+        log::warn!("Failed to change cursor grab: {}", e);
+    }
+    window.set_cursor_visible(!grab);
+}
+
+/// Space pauses, `.` steps a single frame, `+` and `-` change the time scale, and backspace
+/// reverses it. Returns whether `key` was one of these. Holding a toggle doesn't repeat it, holding
+/// `+` or `-` does.
+fn time_control(clock: &mut Clock, key: &Key, repeat: bool) -> bool {
+    match key {
+        Key::Named(NamedKey::Space | NamedKey::Backspace) if repeat => {}
+        Key::Named(NamedKey::Space) => clock.toggle_pause(),
+        Key::Named(NamedKey::Backspace) => clock.reverse(),
+        Key::Character(s) => match s.as_str() {
+            "." if repeat => {}
+            "." => clock.step(),
+            // Plus shares its key with = on most layouts
+            "+" | "=" => clock.speed_up(),
+            "-" => clock.slow_down(),
+            _ => return false,
+        },
+        _ => return false,
+    }
+    log::info!("Simulation {}", clock);
+    true
+}
+
 pub async fn start<F: Framework>(title: &str, args: Cli) {
     init_logger();
 
@@ -393,9 +484,13 @@ pub async fn start<F: Framework>(title: &str, args: Cli) {
     let mut surface = SurfaceWrapper::new();
     let context = Context::init_async::<F>(&mut surface, window_loop.window.clone()).await;
     let mut frame_counter = FrameCounter::new();
+    let mut clock = Clock::from_args(&args);
+    let title = title.to_owned();
+    let mut window_title = String::new();
 
     // We wait to create the example until we have a valid surface.
     let mut scene = None;
+    let mut cursor_grabbed = false;
 
     cfg_if::cfg_if! {
         if #[cfg(target_arch = "wasm32")] {
@@ -486,28 +581,231 @@ pub async fn start<F: Framework>(title: &str, args: Cli) {
                             ..wgpu::TextureViewDescriptor::default()
                         });
 
-                        scene
-                            .as_mut()
-                            .unwrap()
-                            .render(&view, &context.device, &context.queue);
+                        scene.as_mut().unwrap().render(
+                            &view,
+                            &context.device,
+                            &context.queue,
+                            clock.tick(),
+                        );
+
+                        let status = format!("{} ({})", title, clock);
+                        if status != window_title {
+                            window_loop.window.set_title(&status);
+                            window_title = status;
+                        }
 
                         frame.present();
 
                         window_loop.window.request_redraw();
                     }
-                    _ => scene.as_mut().unwrap().update(event),
+                    _ => {
+                        if let WindowEvent::KeyboardInput {
+                            event:
+                                KeyEvent {
+                                    logical_key,
+                                    state: ElementState::Pressed,
+                                    repeat,
+                                    ..
+                                },
+                            ..
+                        } = &event
+                        {
+                            if time_control(&mut clock, logical_key, *repeat) {
+                                return;
+                            }
+                        }
+
+                        let scene = scene.as_mut().unwrap();
+                        scene.update(event);
+
+                        if scene.cursor_grabbed() != cursor_grabbed {
+                            cursor_grabbed = scene.cursor_grabbed();
+                            set_cursor_grab(&window_loop.window, cursor_grabbed);
+                        }
+                    }
                 },
+                Event::DeviceEvent {
+                    event: DeviceEvent::MouseMotion { delta },
+                    ..
+                } => {
+                    if let Some(scene) = scene.as_mut() {
+                        scene.mouse_motion(delta);
+                    }
+                }
                 _ => {}
             }
         },
     );
 }
 
+/// Offscreen color target standing in for the swapchain in headless mode.
+///
+/// The texture is configured the same way [`SurfaceWrapper::resume`] configures
+/// a surface, so [`Framework::init`] and [`Framework::render`] can't tell the difference.
+pub struct OffscreenTarget {
+    texture: wgpu::Texture,
+    config: wgpu::SurfaceConfiguration,
+}
+
+impl OffscreenTarget {
+    pub fn new(context: &Context, width: u32, height: u32, srgb: bool) -> Self {
+        // We pick the format ourselves, so we can make it sRGB directly instead of
+        // going through view formats, which not every backend supports for plain textures.
+        let format = if srgb {
+            wgpu::TextureFormat::Rgba8UnormSrgb
+        } else {
+            wgpu::TextureFormat::Rgba8Unorm
+        };
+
+        let config = wgpu::SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            format,
+            width: width.max(1),
+            height: height.max(1),
+            present_mode: wgpu::PresentMode::Fifo,
+            desired_maximum_frame_latency: 2,
+            alpha_mode: wgpu::CompositeAlphaMode::Opaque,
+            view_formats: vec![format],
+        };
+
+        let texture = context.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Offscreen Target"),
+            size: wgpu::Extent3d {
+                width: config.width,
+                height: config.height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: config.usage,
+            view_formats: &[],
+        });
+
+        Self { texture, config }
+    }
+
+    pub fn config(&self) -> &wgpu::SurfaceConfiguration {
+        &self.config
+    }
+
+    pub fn view(&self) -> wgpu::TextureView {
+        self.texture.create_view(&wgpu::TextureViewDescriptor {
+            format: Some(self.config.view_formats[0]),
+            ..wgpu::TextureViewDescriptor::default()
+        })
+    }
+
+    /// Copy the target back to the CPU as tightly packed RGBA8 rows.
+    pub fn read_pixels(&self, context: &Context) -> Result<Vec<u8>, anyhow::Error> {
+        let width = self.config.width;
+        let height = self.config.height;
+
+        // Rows of a texture to buffer copy must be aligned to 256 bytes.
+        let unpadded_bytes_per_row = width * 4;
+        let padded_bytes_per_row = unpadded_bytes_per_row
+            .div_ceil(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT)
+            * wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+
+        let buffer = context.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Offscreen Readback Buffer"),
+            size: (padded_bytes_per_row * height) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = context
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        encoder.copy_texture_to_buffer(
+            self.texture.as_image_copy(),
+            wgpu::ImageCopyBuffer {
+                buffer: &buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(padded_bytes_per_row),
+                    rows_per_image: None,
+                },
+            },
+            self.texture.size(),
+        );
+        context.queue.submit(std::iter::once(encoder.finish()));
+
+        let slice = buffer.slice(..);
+        let (sender, receiver) = std::sync::mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |result| {
+            let _ = sender.send(result);
+        });
+        context.device.poll(wgpu::Maintain::Wait).panic_on_timeout();
+        receiver.recv()??;
+
+        let mut pixels = Vec::with_capacity((unpadded_bytes_per_row * height) as usize);
+        {
+            let data = slice.get_mapped_range();
+            for row in data.chunks(padded_bytes_per_row as usize) {
+                pixels.extend_from_slice(&row[..unpadded_bytes_per_row as usize]);
+            }
+        }
+        buffer.unmap();
+
+        Ok(pixels)
+    }
+}
+
+/// Render the framework without a window and write the last frame to a PNG file.
+#[cfg(not(target_arch = "wasm32"))]
+pub async fn start_headless<F: Framework>(args: Cli) -> Result<(), anyhow::Error> {
+    init_logger();
+
+    let context = Context::init_headless_async::<F>().await;
+    let target = OffscreenTarget::new(&context, args.width, args.height, F::SRGB);
+
+    let mut scene = F::init(
+        &args,
+        target.config(),
+        &context.adapter,
+        &context.device,
+        &context.queue,
+    )?;
+
+    let view = target.view();
+    let mut clock = Clock::from_args(&args);
+    for _ in 0..args.frames.max(1) {
+        scene.render(&view, &context.device, &context.queue, clock.tick());
+    }
+
+    let pixels = target.read_pixels(&context)?;
+    let image = image::RgbaImage::from_raw(target.config().width, target.config().height, pixels)
+        .ok_or_else(|| anyhow::anyhow!("Readback buffer does not match the target size"))?;
+    image
+        .save(&args.output)
+        .map_err(|e| anyhow::anyhow!("Failed to write {}: {}", args.output.display(), e))?;
+
+    log::info!("Wrote {}", args.output.display());
+    Ok(())
+}
+
 pub fn run<F: Framework>(title: &'static str, args: Cli) {
     cfg_if::cfg_if! {
         if #[cfg(target_arch = "wasm32")] {
             wasm_bindgen_futures::spawn_local(async move { start::<F>(title, args).await })
         } else {
+            if args.reference {
+                init_logger();
+                if let Err(e) = crate::reference::render_to_file(&args) {
+                    log::error!("Reference rendering failed: {:#}", e);
+                    std::process::exit(1);
+                }
+                return;
+            }
+            if args.headless {
+                if let Err(e) = pollster::block_on(start_headless::<F>(args)) {
+                    log::error!("Headless rendering failed: {:#}", e);
+                    std::process::exit(1);
+                }
+                return;
+            }
             pollster::block_on(start::<F>(title, args));
         }
     }
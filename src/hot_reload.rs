@@ -0,0 +1,64 @@
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+/// The shader source in the repository, read by `--watch-shader`.
+pub const SHADER_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/src/shader.wgsl");
+
+// Checking the modification time every frame is wasteful, a few times a second feels instant.
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Notices when a file on disk changes by polling its modification time.
+pub struct ShaderWatcher {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    last_poll: Option<web_time::Instant>,
+}
+
+impl ShaderWatcher {
+    /// The first [`poll`](Self::poll) always reads the file, so the app starts with the source on disk.
+    pub fn new(path: &Path) -> Self {
+        log::info!("Watching {} for changes", path.display());
+        Self {
+            path: path.to_path_buf(),
+            modified: None,
+            last_poll: None,
+        }
+    }
+
+    /// The new file contents if the file changed since the last call.
+    pub fn poll(&mut self) -> Option<String> {
+        let now = web_time::Instant::now();
+        if self
+            .last_poll
+            .is_some_and(|last_poll| now - last_poll < POLL_INTERVAL)
+        {
+            return None;
+        }
+        self.last_poll = Some(now);
+
+        let modified = match std::fs::metadata(&self.path).and_then(|m| m.modified()) {
+            Ok(modified) => modified,
+            Err(e) => {
+                // Editors often replace the file on save, it'll be back on the next poll.
+                log::debug!("Failed to stat {}: {}", self.path.display(), e);
+                return None;
+            }
+        };
+        if self.modified == Some(modified) {
+            return None;
+        }
+
+        match std::fs::read_to_string(&self.path) {
+            Ok(source) => {
+                self.modified = Some(modified);
+                Some(source)
+            }
+            Err(e) => {
+                log::warn!("Failed to read {}: {}", self.path.display(), e);
+                None
+            }
+        }
+    }
+}
@@ -1,23 +1,119 @@
+use std::{collections::HashMap, path::PathBuf};
+
 use bytemuck::{Pod, Zeroable};
 
 use clap::Parser;
 use wgpu::util::DeviceExt;
 
+pub mod blackbody;
 pub mod camera;
+pub mod clock;
+pub mod config;
 pub mod framework;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod hot_reload;
+pub mod lines;
+pub mod model;
+pub mod nbody;
+pub mod orbit;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod reference;
 pub mod scene;
+pub mod scene_graph;
+pub mod skybox;
+pub mod texture;
+pub mod uniforms;
 
+/// Vertex of untextured meshes.
 #[derive(Clone, Copy, Pod, Zeroable)]
 #[repr(C)]
 pub struct Vertex {
-    pos: [f32; 3],
-    normal: [f32; 3],
+    pub pos: [f32; 3],
+    pub normal: [f32; 3],
+}
+
+/// Vertex of meshes with an albedo or normal map.
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+#[repr(C)]
+pub struct TexturedVertex {
+    pub pos: [f32; 3],
+    pub normal: [f32; 3],
+    /// Texture coordinates, with v running down the image.
+    pub uv: [f32; 2],
+    /// Points towards increasing u. The bitangent, which points up the texture like the green
+    /// channel of a normal map, is `w * cross(normal, tangent)`, as in MikkTSpace.
+    pub tangent: [f32; 4],
+}
+
+impl From<TexturedVertex> for Vertex {
+    fn from(vertex: TexturedVertex) -> Self {
+        Self {
+            pos: vertex.pos,
+            normal: vertex.normal,
+        }
+    }
+}
+
+/// Placement of an entity in the world.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Transform {
+    pub position: glam::Vec3,
+    pub rotation: glam::Quat,
+    pub scale: glam::Vec3,
+}
+
+impl Transform {
+    pub const IDENTITY: Self = Self {
+        position: glam::Vec3::ZERO,
+        rotation: glam::Quat::IDENTITY,
+        scale: glam::Vec3::ONE,
+    };
+
+    pub fn from_position(position: glam::Vec3) -> Self {
+        Self {
+            position,
+            ..Self::IDENTITY
+        }
+    }
+
+    pub fn to_matrix(&self) -> glam::Mat4 {
+        glam::Mat4::from_scale_rotation_translation(self.scale, self.rotation, self.position)
+    }
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+/// Surface parameters of an entity.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Material {
+    /// Linear RGB tint of the lit surface.
+    pub color: glam::Vec3,
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        Self {
+            color: glam::Vec3::ONE,
+        }
+    }
 }
 
 pub struct Entity {
     pub vertex_count: u32,
+    /// Holds [`TexturedVertex`]es if the entity has `textures`, [`Vertex`]es otherwise.
     pub vertex_buf: wgpu::Buffer,
     pub index_buf: wgpu::Buffer,
+    /// Albedo and normal map, bound at group 2.
+    pub textures: Option<wgpu::BindGroup>,
+    /// Node the entity hangs from, it sits in world space without one.
+    pub node: Option<scene_graph::NodeId>,
+    /// Relative to the node.
+    pub transform: Transform,
+    pub material: Material,
 }
 
 const DEFAULT_RADIUS: f32 = 15.0;
@@ -28,7 +124,7 @@ pub fn generate_sphere(
     stacks: Option<u32>,
     slices: Option<u32>,
     // sphere_position: glam::Vec3,
-) -> (Vec<Vertex>, Vec<u32>) {
+) -> (Vec<TexturedVertex>, Vec<u32>) {
     let mut vertices = Vec::new();
     let mut indices = Vec::new();
 
@@ -61,7 +157,16 @@ pub fn generate_sphere(
 
             let pos = [x, stack_y, z];
             let normal = [x / r, stack_y / r, z / r];
-            vertices.push(Vertex { pos, normal });
+            // The slice angle turns west, so u counts down to run east and textures aren't
+            // mirrored. Tangents follow u, even at the poles where the triangles collapse.
+            let uv = [1.0 - slice as f32 / slc as f32, stack as f32 / stx as f32];
+            let tangent = [slice_angle.sin(), 0.0, -slice_angle.cos(), 1.0];
+            vertices.push(TexturedVertex {
+                pos,
+                normal,
+                uv,
+                tangent,
+            });
 
             // Generate indices for triangle faces
             if stack != stx && slice != slc {
@@ -84,9 +189,217 @@ pub fn generate_sphere(
     (vertices, indices)
 }
 
-pub fn create_sphere_entity(
+const DEFAULT_SUBDIVISIONS: u32 = 4;
+const DEFAULT_RESOLUTION: u32 = 16;
+
+/// Sphere from an icosahedron whose triangles are split in four `subdivisions` times, so all
+/// triangles have nearly the same size and shape. Texture coordinates wrap around the sphere
+/// like on `generate_sphere`. Vertices on the seam and at the poles are duplicated for that,
+/// everywhere else they're shared.
+pub fn generate_icosphere(
+    radius: Option<f32>,
+    subdivisions: Option<u32>,
+) -> (Vec<TexturedVertex>, Vec<u32>) {
+    let r = radius.unwrap_or(DEFAULT_RADIUS);
+
+    let t = (1.0 + 5f32.sqrt()) / 2.0;
+    let mut directions: Vec<glam::Vec3> = [
+        [-1.0, t, 0.0],
+        [1.0, t, 0.0],
+        [-1.0, -t, 0.0],
+        [1.0, -t, 0.0],
+        [0.0, -1.0, t],
+        [0.0, 1.0, t],
+        [0.0, -1.0, -t],
+        [0.0, 1.0, -t],
+        [t, 0.0, -1.0],
+        [t, 0.0, 1.0],
+        [-t, 0.0, -1.0],
+        [-t, 0.0, 1.0],
+    ]
+    .into_iter()
+    .map(|corner| glam::Vec3::from(corner).normalize())
+    .collect();
+    // Wound like `generate_sphere`, clockwise seen from outside
+    let mut triangles: Vec<[u32; 3]> = vec![
+        [0, 5, 11],
+        [0, 1, 5],
+        [0, 7, 1],
+        [0, 10, 7],
+        [0, 11, 10],
+        [1, 9, 5],
+        [5, 4, 11],
+        [11, 2, 10],
+        [10, 6, 7],
+        [7, 8, 1],
+        [3, 4, 9],
+        [3, 2, 4],
+        [3, 6, 2],
+        [3, 8, 6],
+        [3, 9, 8],
+        [4, 5, 9],
+        [2, 11, 4],
+        [6, 10, 2],
+        [8, 7, 6],
+        [9, 1, 8],
+    ];
+
+    for _ in 0..subdivisions.unwrap_or(DEFAULT_SUBDIVISIONS) {
+        // Neighboring triangles have to share the vertex in the middle of their common edge
+        let mut midpoints: HashMap<(u32, u32), u32> = HashMap::new();
+        let mut midpoint = |a: u32, b: u32| {
+            *midpoints.entry((a.min(b), a.max(b))).or_insert_with(|| {
+                let direction = (directions[a as usize] + directions[b as usize]).normalize();
+                directions.push(direction);
+                directions.len() as u32 - 1
+            })
+        };
+        triangles = triangles
+            .iter()
+            .flat_map(|&[a, b, c]| {
+                let (ab, bc, ca) = (midpoint(a, b), midpoint(b, c), midpoint(c, a));
+                [[a, ab, ca], [b, bc, ab], [c, ca, bc], [ab, bc, ca]]
+            })
+            .collect();
+    }
+
+    let textured_vertex = |direction: glam::Vec3, u: f32| TexturedVertex {
+        pos: (direction * r).to_array(),
+        normal: direction.to_array(),
+        uv: [
+            u,
+            direction.y.clamp(-1.0, 1.0).acos() / std::f32::consts::PI,
+        ],
+        tangent: longitude_tangent(u),
+    };
+    let mut vertices: Vec<TexturedVertex> = directions
+        .iter()
+        .map(|&direction| {
+            let longitude = direction.z.atan2(direction.x) / std::f32::consts::TAU;
+            textured_vertex(direction, 1.0 - longitude.rem_euclid(1.0))
+        })
+        .collect();
+    let is_pole = |i: u32| {
+        let direction = directions[i as usize];
+        direction.x.abs() < 1e-6 && direction.z.abs() < 1e-6
+    };
+
+    // Triangles across the seam would run back over the whole texture, they get copies of the
+    // vertices on its far side with u past 1 instead.
+    let mut seam_copies: HashMap<u32, u32> = HashMap::new();
+    let mut indices = Vec::with_capacity(triangles.len() * 3);
+    for triangle in triangles {
+        let poles = triangle.map(is_pole);
+        let u = triangle.map(|i| vertices[i as usize].uv[0]);
+        let (min_u, max_u) = (0..3)
+            .filter(|&k| !poles[k])
+            .fold((f32::MAX, f32::MIN), |(min, max), k| {
+                (min.min(u[k]), max.max(u[k]))
+            });
+        let mut corners = triangle;
+        for k in 0..3 {
+            if max_u - min_u > 0.5 && !poles[k] && u[k] < 0.5 {
+                corners[k] = *seam_copies.entry(corners[k]).or_insert_with(|| {
+                    vertices.push(textured_vertex(
+                        directions[triangle[k] as usize],
+                        u[k] + 1.0,
+                    ));
+                    vertices.len() as u32 - 1
+                });
+            }
+        }
+
+        // Every longitude meets at a pole, so each triangle there gets its own pole vertex
+        // halfway between the other two corners.
+        if let Some(k) = (0..3).find(|&k| poles[k]) {
+            let others = [corners[(k + 1) % 3], corners[(k + 2) % 3]];
+            let u = others.map(|i| vertices[i as usize].uv[0]);
+            vertices.push(textured_vertex(
+                directions[triangle[k] as usize],
+                (u[0] + u[1]) / 2.0,
+            ));
+            corners[k] = vertices.len() as u32 - 1;
+        }
+        indices.extend(corners);
+    }
+    (vertices, indices)
+}
+
+/// Tangent of a sphere at texture coordinate `u`, pointing east like u on `generate_sphere`.
+fn longitude_tangent(u: f32) -> [f32; 4] {
+    let (sin, cos) = (std::f32::consts::TAU * u).sin_cos();
+    [-sin, 0.0, -cos, 1.0]
+}
+
+/// Sphere from a cube with a `resolution` by `resolution` grid on each face, spread onto the
+/// sphere so the cells keep similar sizes. Every face has its own vertices and is covered by the
+/// whole texture, upright when seen from outside. Vertices along the cube's edges sit at the same
+/// positions on both faces, so the surface is closed.
+pub fn generate_cube_sphere(
+    radius: Option<f32>,
+    resolution: Option<u32>,
+) -> (Vec<TexturedVertex>, Vec<u32>) {
+    let r = radius.unwrap_or(DEFAULT_RADIUS);
+    let n = resolution.unwrap_or(DEFAULT_RESOLUTION) as i32;
+
+    // Corner of each face on the integer lattice of the cube, and its grid axes. The cross
+    // product of the axes points out of the cube.
+    let faces = [
+        ([n, 0, 0], [0, 1, 0], [0, 0, 1]),
+        ([0, 0, 0], [0, 0, 1], [0, 1, 0]),
+        ([0, n, 0], [0, 0, 1], [1, 0, 0]),
+        ([0, 0, 0], [1, 0, 0], [0, 0, 1]),
+        ([0, 0, n], [1, 0, 0], [0, 1, 0]),
+        ([0, 0, 0], [0, 1, 0], [1, 0, 0]),
+    ];
+
+    let mut vertices = Vec::new();
+    let mut indices = Vec::new();
+    for (corner, u, v) in faces {
+        let (corner, u, v) = (
+            glam::IVec3::from(corner),
+            glam::IVec3::from(u),
+            glam::IVec3::from(v),
+        );
+        let first = vertices.len() as u32;
+        for i in 0..=n {
+            for j in 0..=n {
+                // Normalizing the cube's points would crowd them towards the face centers
+                let p = (corner + i * u + j * v).as_vec3() * 2.0 / n as f32 - 1.0;
+                let p2 = p * p;
+                let direction = p * glam::Vec3::new(
+                    (1.0 - p2.y / 2.0 - p2.z / 2.0 + p2.y * p2.z / 3.0).sqrt(),
+                    (1.0 - p2.z / 2.0 - p2.x / 2.0 + p2.z * p2.x / 3.0).sqrt(),
+                    (1.0 - p2.x / 2.0 - p2.y / 2.0 + p2.x * p2.y / 3.0).sqrt(),
+                );
+                vertices.push(TexturedVertex {
+                    pos: (direction * r).to_array(),
+                    normal: direction.to_array(),
+                    // The v axis points up the face, and v runs down the image
+                    uv: [i as f32 / n as f32, 1.0 - j as f32 / n as f32],
+                    tangent: [0.0; 4],
+                });
+            }
+        }
+
+        let vertex = |i: i32, j: i32| first + (i * (n + 1) + j) as u32;
+        for i in 0..n {
+            for j in 0..n {
+                let (a, b) = (vertex(i, j), vertex(i + 1, j));
+                let (c, d) = (vertex(i + 1, j + 1), vertex(i, j + 1));
+                // Clockwise seen from outside, like `generate_sphere`
+                indices.extend([a, c, b, a, d, c]);
+            }
+        }
+    }
+    model::generate_tangents(&mut vertices, &indices);
+    (vertices, indices)
+}
+
+/// An entity drawing `vertices`, either [`Vertex`]es or [`TexturedVertex`]es.
+pub fn create_sphere_entity<V: Pod>(
     device: &wgpu::Device,
-    vertices: Vec<Vertex>,
+    vertices: Vec<V>,
     indices: Vec<u32>,
 ) -> Result<Entity, anyhow::Error> {
     // Create vertex buffer from the sphere vertices
@@ -107,17 +420,77 @@ pub fn create_sphere_entity(
         vertex_buf,
         index_buf,
         vertex_count: indices.len() as u32,
+        textures: None,
+        node: None,
+        transform: Transform::default(),
+        material: Material::default(),
     };
 
     Ok(sphere_entity)
 }
 
-pub struct Config {
-    pub sphere_radius: f32,
-    pub sphere_stacks: u32,
-    pub sphere_slices: u32,
-    pub image_size: u32,
-    pub camera_distance: f32,
+/// One entity per mesh in the OBJ file. `textured` ones get [`TexturedVertex`]es, which
+/// needs texture coordinates in the file.
+pub fn create_model_entities(
+    device: &wgpu::Device,
+    path: &std::path::Path,
+    textured: bool,
+) -> Result<Vec<Entity>, anyhow::Error> {
+    let meshes = model::load_obj(path)?;
+
+    let entities = meshes
+        .iter()
+        .map(|mesh| {
+            let contents = if textured {
+                if !mesh.has_uvs {
+                    return Err(anyhow::anyhow!(
+                        "{} in {} has no texture coordinates",
+                        mesh.name,
+                        path.display()
+                    ));
+                }
+                bytemuck::cast_slice(&mesh.vertices).to_vec()
+            } else {
+                let vertices: Vec<Vertex> = mesh.vertices.iter().map(|&v| v.into()).collect();
+                bytemuck::cast_slice(&vertices).to_vec()
+            };
+            let vertex_buf = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some(&format!("{} Vertex Buffer", mesh.name)),
+                contents: &contents,
+                usage: wgpu::BufferUsages::VERTEX,
+            });
+
+            let index_buf = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some(&format!("{} Index Buffer", mesh.name)),
+                contents: bytemuck::cast_slice(&mesh.indices),
+                usage: wgpu::BufferUsages::INDEX,
+            });
+
+            Ok(Entity {
+                vertex_buf,
+                index_buf,
+                vertex_count: mesh.indices.len() as u32,
+                textures: None,
+                node: None,
+                transform: Transform::default(),
+                material: Material::default(),
+            })
+        })
+        .collect::<Result<_, anyhow::Error>>()?;
+
+    Ok(entities)
+}
+
+/// Sphere generators the command line can pick from.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum SphereMesh {
+    /// Stacks and slices from `generate_sphere`, pinched together at the poles
+    #[default]
+    Uv,
+    /// Subdivided icosahedron from `generate_icosphere`
+    Icosphere,
+    /// Gridded cube faces spread onto the sphere, from `generate_cube_sphere`
+    CubeSphere,
 }
 
 #[derive(Parser, Debug)]
@@ -125,18 +498,124 @@ pub struct Config {
 #[command(about = "renders a black hole in a skybox")]
 #[command(long_about = None)]
 pub struct Cli {
+    /// TOML scene file, replacing the sphere, model and camera arguments
+    #[arg(long)]
+    scene: Option<PathBuf>,
+
     #[arg(short, long)]
     sphere_radius: Option<f32>,
 
-    #[arg(short, long)]
+    /// How the sphere is triangulated
+    #[arg(long, value_enum, default_value_t)]
+    sphere_mesh: SphereMesh,
+
+    /// Only used by the UV sphere
+    #[arg(long)]
     sphere_stacks: Option<u32>,
 
-    #[arg(short, long)]
+    /// Only used by the UV sphere
+    #[arg(long)]
     sphere_slices: Option<u32>,
 
-    #[arg(short, long)]
-    image_size: u32,
+    /// Only used by the icosphere
+    #[arg(long)]
+    sphere_subdivisions: Option<u32>,
+
+    /// Grid cells along each cube edge, only used by the cube sphere
+    #[arg(long)]
+    sphere_resolution: Option<u32>,
 
     #[arg(short, long)]
     camera_distance: Option<f32>,
+
+    /// Keep the orbit camera spinning after a drag is released
+    #[arg(long)]
+    camera_inertia: bool,
+
+    /// How quickly the orbit camera slows down with inertia, per second
+    #[arg(long)]
+    camera_damping: Option<f32>,
+
+    /// OBJ model to add to the scene, can be given multiple times
+    #[arg(long = "model")]
+    models: Vec<PathBuf>,
+
+    /// Position of the matching --model as x,y,z, can be given once per model
+    #[arg(long = "model-position", value_parser = parse_vec3, allow_hyphen_values = true)]
+    model_positions: Vec<glam::Vec3>,
+
+    /// Uniform scale of the matching --model, can be given once per model
+    #[arg(long = "model-scale")]
+    model_scales: Vec<f32>,
+
+    /// KTX2 cubemap file, or directory with right/left/top/bottom/front/back face images.
+    /// Defaults to src/assets/images/skybox_src_images in the current directory or next to the
+    /// executable
+    #[arg(long)]
+    skybox: Option<PathBuf>,
+
+    /// Development mode: reload src/shader.wgsl from disk whenever it changes
+    #[cfg(not(target_arch = "wasm32"))]
+    #[arg(long)]
+    watch_shader: bool,
+
+    /// Advance the simulation in whole steps of this many seconds, however fast frames are drawn.
+    /// Headless rendering advances one step per frame and defaults to 1/60
+    #[arg(long, value_parser = parse_step)]
+    fixed_step: Option<f32>,
+
+    /// Simulated seconds per real second, negative values run the simulation backwards
+    #[arg(long, default_value_t = 1.0, allow_hyphen_values = true)]
+    time_scale: f32,
+
+    /// Start with the simulation paused
+    #[arg(long)]
+    paused: bool,
+
+    /// Render offscreen without opening a window and write the result to `--output`
+    #[arg(long)]
+    headless: bool,
+
+    /// Trace the black hole on the CPU instead, without a GPU, and write the result to `--output`
+    #[arg(long)]
+    reference: bool,
+
+    /// PNG file written in headless or reference mode
+    #[arg(long, default_value = "frame.png")]
+    output: PathBuf,
+
+    /// Width of the headless or reference image
+    #[arg(long, default_value_t = 1280)]
+    width: u32,
+
+    /// Height of the headless or reference image
+    #[arg(long, default_value_t = 720)]
+    height: u32,
+
+    /// Number of frames rendered before the headless capture
+    #[arg(long, default_value_t = 1)]
+    frames: u32,
+}
+
+fn parse_step(s: &str) -> Result<f32, String> {
+    let step = s.parse::<f32>().map_err(|e| e.to_string())?;
+    if step.is_finite() && step > 0.0 {
+        Ok(step)
+    } else {
+        Err(format!("must be a positive number of seconds, got {step}"))
+    }
+}
+
+fn parse_vec3(s: &str) -> Result<glam::Vec3, String> {
+    let components = s
+        .split(',')
+        .map(|c| c.trim().parse::<f32>().map_err(|e| format!("{c:?}: {e}")))
+        .collect::<Result<Vec<_>, _>>()?;
+    match components[..] {
+        [x, y, z] => Ok(glam::Vec3::new(x, y, z)),
+        _ => Err(format!(
+            "expected x,y,z, got {} components",
+            components.len()
+        )),
+    }
 }
@@ -0,0 +1,123 @@
+use std::collections::VecDeque;
+
+use bytemuck::{Pod, Zeroable};
+
+// Samples a trail keeps at most, spread over its duration.
+const MAX_TRAIL_SAMPLES: usize = 256;
+
+/// Corner of a line segment quad, which `vs_line` widens in screen space. WebGL2 can only draw
+/// lines one pixel wide, so lines are drawn as triangles.
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+#[repr(C)]
+pub struct LineVertex {
+    start: [f32; 3],
+    end: [f32; 3],
+    color: [f32; 4],
+    /// In pixels.
+    width: f32,
+    /// 0 at the start of the segment, 1 at the end.
+    along: f32,
+    /// Which edge of the quad, -1 or 1.
+    side: f32,
+}
+
+impl LineVertex {
+    pub const ATTRIBUTES: [wgpu::VertexAttribute; 6] = wgpu::vertex_attr_array![
+        0 => Float32x3,
+        1 => Float32x3,
+        2 => Float32x4,
+        3 => Float32,
+        4 => Float32,
+        5 => Float32,
+    ];
+}
+
+/// Point on a line, with the linear RGB color and opacity there.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LinePoint {
+    pub position: glam::Vec3,
+    pub color: glam::Vec4,
+}
+
+/// Append the quads of a line `width` pixels wide through `points`.
+pub fn push_line(vertices: &mut Vec<LineVertex>, points: &[LinePoint], width: f32) {
+    for segment in points.windows(2) {
+        let (start, end) = (segment[0], segment[1]);
+        let corner = |along: f32, side: f32| LineVertex {
+            start: start.position.to_array(),
+            end: end.position.to_array(),
+            color: start.color.lerp(end.color, along).to_array(),
+            width,
+            along,
+            side,
+        };
+        vertices.extend([
+            corner(0.0, -1.0),
+            corner(0.0, 1.0),
+            corner(1.0, 1.0),
+            corner(0.0, -1.0),
+            corner(1.0, 1.0),
+            corner(1.0, -1.0),
+        ]);
+    }
+}
+
+/// Where something has been over the last `duration` simulated seconds.
+pub struct Trail {
+    pub duration: f64,
+    // Simulated time and position, oldest first.
+    samples: VecDeque<(f64, glam::Vec3)>,
+}
+
+impl Trail {
+    pub fn new(duration: f64) -> Self {
+        Self {
+            duration,
+            samples: VecDeque::new(),
+        }
+    }
+
+    /// Remember `position` at `sim_time` if it's been long enough since the last sample.
+    /// Samples after `sim_time` are dropped, so running time backwards rewinds the trail.
+    pub fn record(&mut self, sim_time: f64, position: glam::Vec3) {
+        while self
+            .samples
+            .back()
+            .is_some_and(|&(time, _)| time > sim_time)
+        {
+            self.samples.pop_back();
+        }
+        while self
+            .samples
+            .front()
+            .is_some_and(|&(time, _)| time < sim_time - self.duration)
+        {
+            self.samples.pop_front();
+        }
+
+        let spacing = self.duration / MAX_TRAIL_SAMPLES as f64;
+        if self
+            .samples
+            .back()
+            .is_none_or(|&(time, _)| sim_time - time >= spacing)
+        {
+            self.samples.push_back((sim_time, position));
+        }
+    }
+
+    /// Points from the oldest sample up to `position` at `sim_time`, fading out with age.
+    pub fn points(&self, sim_time: f64, position: glam::Vec3, color: glam::Vec4) -> Vec<LinePoint> {
+        let fade = |time: f64| {
+            let age = ((sim_time - time) / self.duration).clamp(0.0, 1.0) as f32;
+            color * glam::Vec4::new(1.0, 1.0, 1.0, 1.0 - age)
+        };
+        self.samples
+            .iter()
+            .map(|&(time, position)| LinePoint {
+                position,
+                color: fade(time),
+            })
+            .chain(std::iter::once(LinePoint { position, color }))
+            .collect()
+    }
+}
@@ -0,0 +1,218 @@
+use std::{collections::HashMap, path::Path};
+
+use crate::TexturedVertex;
+
+/// Triangulated geometry of one group of an OBJ object.
+pub struct Mesh {
+    pub name: String,
+    pub vertices: Vec<TexturedVertex>,
+    pub indices: Vec<u32>,
+    /// Whether every vertex had texture coordinates. Otherwise they and the tangents are zero.
+    pub has_uvs: bool,
+}
+
+/// Load every object and group of an OBJ file as a separate [`Mesh`].
+///
+/// Polygons are fan triangulated, so they are expected to be convex. Vertices
+/// without a normal get the area weighted average of the faces sharing their position, even
+/// across texture seams.
+/// Tangents are generated from the texture coordinates, if there are any.
+pub fn load_obj(path: &Path) -> Result<Vec<Mesh>, anyhow::Error> {
+    let obj = obj::Obj::load_with_config(path, obj::LoadConfig { strict: false })
+        .map_err(|e| anyhow::anyhow!("Failed to load {}: {}", path.display(), e))?;
+    let data = obj.data;
+
+    let mut meshes = Vec::new();
+    for object in data.objects.iter() {
+        for group in object.groups.iter() {
+            let mesh = triangulate_group(&data, &object.name, group)
+                .map_err(|e| e.context(format!("Invalid OBJ file {}", path.display())))?;
+            if !mesh.indices.is_empty() {
+                meshes.push(mesh);
+            }
+        }
+    }
+
+    if meshes.is_empty() {
+        return Err(anyhow::anyhow!("{} contains no faces", path.display()));
+    }
+
+    log::info!(
+        "Loaded {} meshes with {} triangles from {}",
+        meshes.len(),
+        meshes.iter().map(|m| m.indices.len() / 3).sum::<usize>(),
+        path.display()
+    );
+
+    Ok(meshes)
+}
+
+fn triangulate_group(
+    data: &obj::ObjData,
+    object_name: &str,
+    group: &obj::Group,
+) -> Result<Mesh, anyhow::Error> {
+    let mut vertices: Vec<TexturedVertex> = Vec::new();
+    let mut indices = Vec::new();
+    // OBJ indexes positions, texture coordinates and normals separately, so each distinct
+    // combination becomes one vertex.
+    let mut vertex_ids: HashMap<(usize, Option<usize>, Option<usize>), u32> = HashMap::new();
+    let mut needs_normal = Vec::new();
+    // OBJ position index of each vertex, vertices split along texture seams share it.
+    let mut position_ids = Vec::new();
+    let mut has_uvs = true;
+
+    for polygon in group.polys.iter() {
+        if polygon.0.len() < 3 {
+            continue;
+        }
+
+        let mut corners = Vec::with_capacity(polygon.0.len());
+        for &obj::IndexTuple(position, uv_index, normal_index) in polygon.0.iter() {
+            let pos = *data
+                .position
+                .get(position)
+                .ok_or_else(|| anyhow::anyhow!("Position index {} out of range", position + 1))?;
+            let normal = match normal_index {
+                Some(n) => Some(
+                    *data
+                        .normal
+                        .get(n)
+                        .ok_or_else(|| anyhow::anyhow!("Normal index {} out of range", n + 1))?,
+                ),
+                None => None,
+            };
+            // OBJ's v runs up the image
+            let uv = match uv_index {
+                Some(t) => {
+                    let [u, v] = *data.texture.get(t).ok_or_else(|| {
+                        anyhow::anyhow!("Texture coordinate index {} out of range", t + 1)
+                    })?;
+                    [u, 1.0 - v]
+                }
+                None => {
+                    has_uvs = false;
+                    [0.0; 2]
+                }
+            };
+
+            let id = *vertex_ids
+                .entry((position, uv_index, normal_index))
+                .or_insert_with(|| {
+                    vertices.push(TexturedVertex {
+                        pos,
+                        normal: normal.unwrap_or([0.0; 3]),
+                        uv,
+                        tangent: [0.0; 4],
+                    });
+                    needs_normal.push(normal.is_none());
+                    position_ids.push(position);
+                    (vertices.len() - 1) as u32
+                });
+            corners.push(id);
+        }
+
+        for i in 1..corners.len() - 1 {
+            indices.extend_from_slice(&[corners[0], corners[i], corners[i + 1]]);
+        }
+    }
+
+    if needs_normal.contains(&true) {
+        compute_missing_normals(&mut vertices, &indices, &needs_normal, &position_ids);
+    }
+    if has_uvs {
+        generate_tangents(&mut vertices, &indices);
+    }
+
+    let name = if group.name == object_name {
+        object_name.to_string()
+    } else {
+        format!("{}/{}", object_name, group.name)
+    };
+
+    Ok(Mesh {
+        name,
+        vertices,
+        indices,
+        has_uvs,
+    })
+}
+
+/// Sum up the (area weighted) face normals per position and hand them to the vertices that
+/// came without one. Summing per vertex would crease the shading along texture seams.
+fn compute_missing_normals(
+    vertices: &mut [TexturedVertex],
+    indices: &[u32],
+    needs_normal: &[bool],
+    position_ids: &[usize],
+) {
+    let mut sums: HashMap<usize, glam::Vec3> = HashMap::new();
+    for triangle in indices.chunks_exact(3) {
+        let [a, b, c] = [0, 1, 2].map(|i| glam::Vec3::from(vertices[triangle[i] as usize].pos));
+        let face_normal = (b - a).cross(c - a);
+        for &id in triangle {
+            *sums.entry(position_ids[id as usize]).or_default() += face_normal;
+        }
+    }
+
+    for ((vertex, _), position) in vertices
+        .iter_mut()
+        .zip(needs_normal)
+        .zip(position_ids)
+        .filter(|((_, &needs_normal), _)| needs_normal)
+    {
+        vertex.normal = sums[position]
+            .try_normalize()
+            .unwrap_or(glam::Vec3::Y)
+            .to_array();
+    }
+}
+
+/// MikkTSpace-style tangents: each triangle's tangent and bitangent from its texture
+/// coordinates, weighted by the angle at each corner and made orthogonal to the vertex normal.
+/// Unlike MikkTSpace, vertices on mirrored texture seams aren't split, so they get the average.
+pub fn generate_tangents(vertices: &mut [TexturedVertex], indices: &[u32]) {
+    let mut tangents = vec![glam::Vec3::ZERO; vertices.len()];
+    let mut bitangents = vec![glam::Vec3::ZERO; vertices.len()];
+
+    for triangle in indices.chunks_exact(3) {
+        let positions = [0, 1, 2].map(|i| glam::Vec3::from(vertices[triangle[i] as usize].pos));
+        // Flipping v makes the bitangent point up the texture
+        let uvs = [0, 1, 2].map(|i| {
+            let [u, v] = vertices[triangle[i] as usize].uv;
+            glam::Vec2::new(u, -v)
+        });
+
+        let (e1, e2) = (positions[1] - positions[0], positions[2] - positions[0]);
+        let (d1, d2) = (uvs[1] - uvs[0], uvs[2] - uvs[0]);
+        let det = d1.perp_dot(d2);
+        if det.abs() <= f32::EPSILON * d1.length() * d2.length() {
+            continue;
+        }
+        let tangent = ((e1 * d2.y - e2 * d1.y) / det).normalize_or_zero();
+        let bitangent = ((e2 * d1.x - e1 * d2.x) / det).normalize_or_zero();
+
+        for k in 0..3 {
+            let to_next = positions[(k + 1) % 3] - positions[k];
+            let to_previous = positions[(k + 2) % 3] - positions[k];
+            let angle = to_next.angle_between(to_previous);
+            if angle.is_finite() {
+                tangents[triangle[k] as usize] += angle * tangent;
+                bitangents[triangle[k] as usize] += angle * bitangent;
+            }
+        }
+    }
+
+    for ((vertex, tangent), bitangent) in vertices.iter_mut().zip(tangents).zip(bitangents) {
+        let normal = glam::Vec3::from(vertex.normal);
+        let tangent = (tangent - normal * normal.dot(tangent))
+            .try_normalize()
+            .unwrap_or_else(|| normal.any_orthonormal_vector());
+        let handedness = if normal.cross(tangent).dot(bitangent) < 0.0 {
+            -1.0
+        } else {
+            1.0
+        };
+        vertex.tangent = tangent.extend(handedness).to_array();
+    }
+}
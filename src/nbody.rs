@@ -0,0 +1,363 @@
+use std::collections::VecDeque;
+
+use glam::DVec3;
+use serde::Deserialize;
+
+// More steps than this per frame and the simulation falls behind instead of freezing the app.
+const MAX_STEPS_PER_ADVANCE: u32 = 10_000;
+
+/// How [`Simulation::step`] moves the bodies forward. All of them are symplectic, so energy
+/// errors oscillate instead of building up over long runs, and time-reversible except for
+/// [`Integrator::Euler`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Integrator {
+    /// Semi-implicit Euler, first order with one force evaluation per step.
+    Euler,
+    /// Kick-drift-kick leapfrog, the same as velocity Verlet. Second order.
+    #[default]
+    Leapfrog,
+    /// Yoshida's composition of three leapfrog steps. Fourth order at three times the cost.
+    Yoshida,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Body {
+    pub mass: f64,
+    pub position: DVec3,
+    pub velocity: DVec3,
+}
+
+/// Conserved quantities of the whole system.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Diagnostics {
+    /// Kinetic plus potential energy.
+    pub energy: f64,
+    pub momentum: DVec3,
+    /// Around the origin, where the central mass sits.
+    pub angular_momentum: DVec3,
+}
+
+/// How far the conserved quantities wandered off since the start.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Drift {
+    /// Change in energy relative to the initial energy.
+    pub energy: f64,
+    /// Change in momentum. Only conserved without a central mass, which pulls on everything.
+    pub momentum: f64,
+    /// Change in angular momentum relative to its initial size.
+    pub angular_momentum: f64,
+}
+
+/// Newtonian gravity between bodies, advanced at a fixed time step.
+#[derive(Clone)]
+pub struct Simulation {
+    bodies: Vec<Body>,
+    pub integrator: Integrator,
+    /// Simulated seconds per integration step.
+    pub time_step: f64,
+    /// Plummer softening length, keeps close encounters from flinging bodies away.
+    pub softening: f64,
+    pub gravitational_constant: f64,
+    /// Mass fixed at the origin, e.g. the black hole.
+    pub central_mass: f64,
+    // Simulated time not yet covered by a whole step.
+    accumulator: f64,
+    // Whole steps taken by `advance`, backwards ones counting down.
+    steps: i64,
+    // Reused by every kick, so stepping doesn't allocate.
+    accelerations: Vec<DVec3>,
+    initial: Diagnostics,
+}
+
+impl Simulation {
+    pub fn new(integrator: Integrator, time_step: f64) -> Self {
+        Self {
+            bodies: Vec::new(),
+            integrator,
+            time_step,
+            softening: 0.0,
+            gravitational_constant: 1.0,
+            central_mass: 0.0,
+            accumulator: 0.0,
+            steps: 0,
+            accelerations: Vec::new(),
+            initial: Diagnostics {
+                energy: 0.0,
+                momentum: DVec3::ZERO,
+                angular_momentum: DVec3::ZERO,
+            },
+        }
+    }
+
+    /// Add a body and return its index. Drift is measured from the bodies added so far.
+    pub fn add_body(&mut self, body: Body) -> usize {
+        self.bodies.push(body);
+        self.initial = self.diagnostics();
+        self.bodies.len() - 1
+    }
+
+    pub fn bodies(&self) -> &[Body] {
+        &self.bodies
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bodies.is_empty()
+    }
+
+    /// Take as many whole steps as fit into `dt` simulated seconds, carrying the rest over to
+    /// the next call. Negative `dt` runs the simulation backwards. Steps beyond the per-call
+    /// budget are carried over too, see [`Simulation::lag`].
+    pub fn advance(&mut self, dt: f64) {
+        if self.bodies.is_empty() || self.time_step <= 0.0 {
+            return;
+        }
+
+        let was_behind = self.lag().abs() >= self.time_step;
+        self.accumulator += dt;
+        let steps = (self.accumulator.abs() / self.time_step).floor();
+        let step = self.time_step.copysign(self.accumulator);
+        let taken = steps.min(MAX_STEPS_PER_ADVANCE as f64);
+        self.accumulator -= taken * step;
+
+        if steps > taken && !was_behind {
+            log::warn!(
+                "N-body simulation is {} steps behind, catching up over the next frames",
+                steps - taken
+            );
+        }
+        for _ in 0..taken as u32 {
+            self.step(step);
+            self.steps += step.signum() as i64;
+        }
+    }
+
+    /// Simulated seconds handed to [`Simulation::advance`] but not stepped through yet. Grows
+    /// while the simulation can't keep up with the time scale.
+    pub fn lag(&self) -> f64 {
+        self.accumulator
+    }
+
+    /// Advance all bodies by a single step of `dt` seconds with the configured integrator.
+    pub fn step(&mut self, dt: f64) {
+        match self.integrator {
+            Integrator::Euler => {
+                self.kick(dt);
+                self.drift(dt);
+            }
+            Integrator::Leapfrog => self.leapfrog(dt),
+            Integrator::Yoshida => {
+                let cbrt2 = 2f64.cbrt();
+                let w1 = 1.0 / (2.0 - cbrt2);
+                let w0 = -cbrt2 * w1;
+                self.leapfrog(w1 * dt);
+                self.leapfrog(w0 * dt);
+                self.leapfrog(w1 * dt);
+            }
+        }
+    }
+
+    pub fn diagnostics(&self) -> Diagnostics {
+        let g = self.gravitational_constant;
+        let mut energy = 0.0;
+        let mut momentum = DVec3::ZERO;
+        let mut angular_momentum = DVec3::ZERO;
+
+        for (i, body) in self.bodies.iter().enumerate() {
+            energy += 0.5 * body.mass * body.velocity.length_squared();
+            energy -= g * self.central_mass * body.mass / self.softened_distance(body.position);
+            for other in &self.bodies[i + 1..] {
+                energy -= g * body.mass * other.mass
+                    / self.softened_distance(other.position - body.position);
+            }
+            momentum += body.mass * body.velocity;
+            angular_momentum += body.mass * body.position.cross(body.velocity);
+        }
+
+        Diagnostics {
+            energy,
+            momentum,
+            angular_momentum,
+        }
+    }
+
+    pub fn drift_since_start(&self) -> Drift {
+        let now = self.diagnostics();
+        let relative = |change: f64, scale: f64| {
+            if scale > 0.0 {
+                change / scale
+            } else {
+                change
+            }
+        };
+        Drift {
+            energy: relative(now.energy - self.initial.energy, self.initial.energy.abs()),
+            momentum: (now.momentum - self.initial.momentum).length(),
+            angular_momentum: relative(
+                (now.angular_momentum - self.initial.angular_momentum).length(),
+                self.initial.angular_momentum.length(),
+            ),
+        }
+    }
+
+    fn leapfrog(&mut self, dt: f64) {
+        self.kick(0.5 * dt);
+        self.drift(dt);
+        self.kick(0.5 * dt);
+    }
+
+    fn kick(&mut self, dt: f64) {
+        let mut accelerations = std::mem::take(&mut self.accelerations);
+        self.compute_accelerations(&mut accelerations);
+        for (body, acceleration) in self.bodies.iter_mut().zip(&accelerations) {
+            body.velocity += dt * *acceleration;
+        }
+        self.accelerations = accelerations;
+    }
+
+    fn drift(&mut self, dt: f64) {
+        for body in self.bodies.iter_mut() {
+            body.position += dt * body.velocity;
+        }
+    }
+
+    fn compute_accelerations(&self, accelerations: &mut Vec<DVec3>) {
+        let g = self.gravitational_constant;
+        accelerations.clear();
+        accelerations.extend(self.bodies.iter().map(|body| {
+            -g * self.central_mass * body.position / self.softened_cube(body.position)
+        }));
+
+        for i in 0..self.bodies.len() {
+            for j in i + 1..self.bodies.len() {
+                let offset = self.bodies[j].position - self.bodies[i].position;
+                let pull = g * offset / self.softened_cube(offset);
+                accelerations[i] += self.bodies[j].mass * pull;
+                accelerations[j] -= self.bodies[i].mass * pull;
+            }
+        }
+    }
+
+    fn softened_distance(&self, offset: DVec3) -> f64 {
+        (offset.length_squared() + self.softening * self.softening).sqrt()
+    }
+
+    fn softened_cube(&self, offset: DVec3) -> f64 {
+        self.softened_distance(offset).powi(3)
+    }
+
+    fn positions(&self) -> Vec<DVec3> {
+        self.bodies.iter().map(|body| body.position).collect()
+    }
+}
+
+/// Where the bodies of a [`Simulation`] are headed, from a copy of it running ahead. The copy
+/// takes the same steps as [`Simulation::advance`], so the paths are exactly where the bodies
+/// will go, and only need extending as the simulation catches up.
+pub struct Prediction {
+    future: Simulation,
+    backwards: bool,
+    steps_per_sample: i64,
+    // Step of the simulation the first sample was taken at.
+    first_step: i64,
+    // Positions of the bodies, `steps_per_sample` apart. `future` is at the last one.
+    samples: VecDeque<Vec<DVec3>>,
+}
+
+impl Prediction {
+    /// Predict from where `simulation` is now, sampling every `interval` seconds, rounded to
+    /// whole steps. `backwards` follows the bodies back in time instead.
+    pub fn new(simulation: &Simulation, interval: f64, backwards: bool) -> Self {
+        Self {
+            future: simulation.clone(),
+            backwards,
+            steps_per_sample: Self::steps_per_sample(simulation, interval),
+            first_step: simulation.steps,
+            samples: VecDeque::from([simulation.positions()]),
+        }
+    }
+
+    fn steps_per_sample(simulation: &Simulation, interval: f64) -> i64 {
+        if simulation.time_step > 0.0 {
+            ((interval / simulation.time_step).round() as i64).max(1)
+        } else {
+            1
+        }
+    }
+
+    pub fn is_backwards(&self) -> bool {
+        self.backwards
+    }
+
+    // 1 forwards, -1 backwards.
+    fn direction(&self) -> i64 {
+        if self.backwards {
+            -1
+        } else {
+            1
+        }
+    }
+
+    fn last_step(&self) -> i64 {
+        self.first_step + self.direction() * self.steps_per_sample * (self.samples.len() as i64 - 1)
+    }
+
+    /// Drop what `simulation` has passed, and extend the paths to `duration` seconds ahead of it.
+    /// Starts over if the simulation left the predicted path or the settings changed.
+    pub fn update(
+        &mut self,
+        simulation: &Simulation,
+        duration: f64,
+        interval: f64,
+        backwards: bool,
+    ) {
+        let direction = self.direction();
+        let on_path = (simulation.steps - self.first_step) * direction >= 0
+            && (self.last_step() - simulation.steps) * direction >= 0;
+        if !on_path
+            || backwards != self.backwards
+            || simulation.time_step != self.future.time_step
+            || Self::steps_per_sample(simulation, interval) != self.steps_per_sample
+        {
+            *self = Self::new(simulation, interval, backwards);
+        }
+        if simulation.time_step <= 0.0 {
+            return;
+        }
+
+        // Keep the last sample at or behind the simulation, the path starts between it and the next
+        let direction = self.direction();
+        while self.samples.len() > 1
+            && (simulation.steps - self.first_step - direction * self.steps_per_sample) * direction
+                >= 0
+        {
+            self.samples.pop_front();
+            self.first_step += direction * self.steps_per_sample;
+        }
+
+        let steps_ahead = (duration / simulation.time_step).ceil() as i64;
+        let step = simulation.time_step * direction as f64;
+        let mut budget = MAX_STEPS_PER_ADVANCE as i64;
+        while (self.last_step() - simulation.steps) * direction < steps_ahead && budget > 0 {
+            for _ in 0..self.steps_per_sample {
+                self.future.step(step);
+            }
+            budget -= self.steps_per_sample;
+            self.samples.push_back(self.future.positions());
+        }
+    }
+
+    /// Path of each body, from where it is in `simulation` now, then through the samples ahead.
+    pub fn paths(&self, simulation: &Simulation) -> Vec<Vec<DVec3>> {
+        simulation
+            .bodies
+            .iter()
+            .enumerate()
+            .map(|(i, body)| {
+                std::iter::once(body.position)
+                    .chain(self.samples.iter().skip(1).map(|sample| sample[i]))
+                    .collect()
+            })
+            .collect()
+    }
+}
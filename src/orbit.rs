@@ -0,0 +1,75 @@
+use std::f64::consts::TAU;
+
+use glam::{DQuat, DVec3};
+
+// Newton iterations for Kepler's equation, enough for f64 precision up to eccentricities
+// close to 1.
+const KEPLER_ITERATIONS: u32 = 30;
+
+/// Elliptical orbit around a parent, from classical orbital elements.
+///
+/// The reference plane is the xz plane, so the black hole's disk, and prograde orbits run
+/// counterclockwise when seen from above, like the disk around a hole with positive spin.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct KeplerOrbit {
+    pub semi_major_axis: f64,
+    /// Between 0 for a circle and 1, exclusive.
+    pub eccentricity: f64,
+    /// Angles in radians.
+    pub inclination: f64,
+    pub longitude_of_ascending_node: f64,
+    pub argument_of_periapsis: f64,
+    /// Where on the orbit the entity is at time 0.
+    pub mean_anomaly_at_epoch: f64,
+    /// Seconds per lap.
+    pub period: f64,
+}
+
+impl KeplerOrbit {
+    /// Period of an orbit with `semi_major_axis` around a parent with gravitational parameter
+    /// `mu`, which is G times its mass.
+    pub fn period_around(semi_major_axis: f64, mu: f64) -> f64 {
+        TAU * (semi_major_axis.powi(3) / mu).sqrt()
+    }
+
+    pub fn mean_anomaly(&self, time: f64) -> f64 {
+        self.mean_anomaly_at_epoch + TAU * time / self.period
+    }
+
+    /// Offset from the parent at `time` seconds.
+    pub fn position(&self, time: f64) -> DVec3 {
+        let e = self.eccentricity;
+        let eccentric_anomaly = solve_kepler(self.mean_anomaly(time), e);
+        let (sin_e, cos_e) = (0.5 * eccentric_anomaly).sin_cos();
+        let true_anomaly = 2.0 * ((1.0 + e).sqrt() * sin_e).atan2((1.0 - e).sqrt() * cos_e);
+        let radius = self.semi_major_axis * (1.0 - e * eccentric_anomaly.cos());
+
+        // Rotations around y run counterclockwise seen from above, the nodes lie on the x axis
+        // before the longitude of the ascending node turns them.
+        let orientation = DQuat::from_rotation_y(self.longitude_of_ascending_node)
+            * DQuat::from_rotation_x(self.inclination)
+            * DQuat::from_rotation_y(self.argument_of_periapsis + true_anomaly);
+        orientation * DVec3::new(radius, 0.0, 0.0)
+    }
+}
+
+/// Eccentric anomaly E with E - e sin E = `mean_anomaly`, for elliptical orbits.
+pub fn solve_kepler(mean_anomaly: f64, eccentricity: f64) -> f64 {
+    let mean_anomaly = mean_anomaly.rem_euclid(TAU);
+    // Newton's method can overshoot from M on very eccentric orbits, it never does from π
+    let mut eccentric_anomaly = if eccentricity > 0.8 {
+        std::f64::consts::PI
+    } else {
+        mean_anomaly
+    };
+    for _ in 0..KEPLER_ITERATIONS {
+        let error = eccentric_anomaly - eccentricity * eccentric_anomaly.sin() - mean_anomaly;
+        let derivative = 1.0 - eccentricity * eccentric_anomaly.cos();
+        let delta = error / derivative;
+        eccentric_anomaly -= delta;
+        if delta.abs() < 1e-14 {
+            break;
+        }
+    }
+    eccentric_anomaly
+}
@@ -0,0 +1,510 @@
+use std::path::Path;
+
+use glam::{Mat3, Mat4, Vec2, Vec3, Vec4};
+
+use crate::{
+    blackbody,
+    camera::Camera,
+    config::{BlackHoleConfig, Config, Metric},
+    skybox::{self, SkyboxSource},
+    Cli,
+};
+
+// The constants and functions below mirror their namesakes in shader.wgsl, keep them in sync.
+
+const ESCAPE_RADIUS: f32 = 1000.0;
+const HALF_PI: f32 = std::f32::consts::FRAC_PI_2;
+const DISK_TEMPERATURE: f32 = 39290.0;
+const EXPOSURE_TEMPERATURE: f32 = 5000.0;
+
+/// Render the black hole in the scene from `--scene` or the command line on the CPU and write it
+/// to `--output`.
+///
+/// Entities are left out, and the skybox has to be a directory of face images.
+pub fn render_to_file(args: &Cli) -> Result<(), anyhow::Error> {
+    let config = match &args.scene {
+        Some(path) => Config::load(path)?,
+        None => Config::from_cli(args)?,
+    };
+    if !config.entities.is_empty() {
+        log::info!(
+            "The reference renderer skips the {} entities in the scene",
+            config.entities.len()
+        );
+    }
+
+    // --skybox wins over the scene file.
+    let skybox_source = args
+        .skybox
+        .as_deref()
+        .or(config.skybox.as_deref())
+        .map_or_else(SkyboxSource::find_default, |path| {
+            Ok(SkyboxSource::from_path(path))
+        })?;
+    let sky = match &skybox_source {
+        SkyboxSource::Directory(dir) => CubeMap::load(dir)?,
+        source => {
+            return Err(anyhow::anyhow!(
+                "The reference renderer only reads skybox face images, not {:?}",
+                source
+            ))
+        }
+    };
+
+    let camera = config.camera.create_camera((args.width, args.height));
+    let image = render(&camera, &config.black_hole, &sky);
+    image
+        .save(&args.output)
+        .map_err(|e| anyhow::anyhow!("Failed to write {}: {}", args.output.display(), e))?;
+
+    log::info!("Wrote {}", args.output.display());
+    Ok(())
+}
+
+/// Trace one ray per pixel of `camera.screen_size`, the way `fs_universe` does.
+pub fn render(camera: &Camera, black_hole: &BlackHoleConfig, sky: &CubeMap) -> image::RgbaImage {
+    let (width, height) = camera.screen_size;
+    let rays = RayCamera::new(camera);
+    let tracer = Tracer::new(black_hole);
+
+    let mut image = image::RgbaImage::new(width, height);
+    let row_bytes = width as usize * 4;
+    if row_bytes == 0 {
+        return image;
+    }
+    let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+    let rows_per_thread = (height as usize).div_ceil(threads).max(1);
+
+    std::thread::scope(|scope| {
+        for (chunk_index, chunk) in image.chunks_mut(rows_per_thread * row_bytes).enumerate() {
+            let (rays, tracer) = (&rays, &tracer);
+            scope.spawn(move || {
+                for (row_index, row) in chunk.chunks_mut(row_bytes).enumerate() {
+                    let y = (chunk_index * rows_per_thread + row_index) as u32;
+                    for (x, pixel) in row.chunks_mut(4).enumerate() {
+                        let direction = rays.direction(x as u32, y, width, height);
+                        let color = tracer.shade(rays.origin, direction, sky);
+                        let [r, g, b] = color.to_array().map(blackbody::linear_to_srgb);
+                        pixel.copy_from_slice(&[r, g, b, 255]);
+                    }
+                }
+            });
+        }
+    });
+
+    image
+}
+
+/// Camera rays built from the same matrices [`Camera::to_uniform_data`] hands the shader.
+pub struct RayCamera {
+    pub origin: Vec3,
+    proj_inv: Mat4,
+    view_rotation_inv: Mat3,
+}
+
+impl RayCamera {
+    pub fn new(camera: &Camera) -> Self {
+        let data = camera.to_uniform_data();
+        let view = Mat4::from_cols_array_2d(&data.view);
+        Self {
+            origin: Vec4::from(data.position).truncate(),
+            proj_inv: Mat4::from_cols_array_2d(&data.proj_inv),
+            // The view matrix only rotates and translates, so the transpose undoes its rotation
+            view_rotation_inv: Mat3::from_mat4(view).transpose(),
+        }
+    }
+
+    /// World space direction through the center of pixel (`x`, `y`), counted from the top left.
+    pub fn direction(&self, x: u32, y: u32, width: u32, height: u32) -> Vec3 {
+        let ndc = Vec2::new(
+            (x as f32 + 0.5) / width as f32 * 2.0 - 1.0,
+            1.0 - (y as f32 + 0.5) / height as f32 * 2.0,
+        );
+        // Point on the far plane in camera space
+        let far = self.proj_inv * Vec4::new(ndc.x, ndc.y, 1.0, 1.0);
+        (self.view_rotation_inv * (far.truncate() / far.w)).normalize()
+    }
+}
+
+/// Cubemap in linear color, sampled like a `texture_cube` with linear filtering at mip level 0.
+pub struct CubeMap {
+    size: u32,
+    faces: Vec<Vec<Vec3>>,
+}
+
+impl CubeMap {
+    /// Read the sRGB face images in `dir`, see [`skybox::FACE_NAMES`].
+    pub fn load(dir: &Path) -> Result<Self, anyhow::Error> {
+        let faces = skybox::load_faces(dir)?;
+        let size = faces[0].width();
+        let faces = faces
+            .iter()
+            .map(|face| {
+                face.pixels()
+                    .map(|pixel| {
+                        Vec3::from([pixel[0], pixel[1], pixel[2]].map(blackbody::srgb_to_linear))
+                    })
+                    .collect()
+            })
+            .collect();
+        Ok(Self { size, faces })
+    }
+
+    pub fn sample(&self, direction: Vec3) -> Vec3 {
+        // Face selection and coordinates from the cube map table of the Vulkan and WebGPU specs
+        let abs = direction.abs();
+        let (face, sc, tc, ma) = if abs.x >= abs.y && abs.x >= abs.z {
+            if direction.x > 0.0 {
+                (0, -direction.z, -direction.y, abs.x)
+            } else {
+                (1, direction.z, -direction.y, abs.x)
+            }
+        } else if abs.y >= abs.z {
+            if direction.y > 0.0 {
+                (2, direction.x, direction.z, abs.y)
+            } else {
+                (3, direction.x, -direction.z, abs.y)
+            }
+        } else if direction.z > 0.0 {
+            (4, direction.x, -direction.y, abs.z)
+        } else {
+            (5, -direction.x, -direction.y, abs.z)
+        };
+
+        let size = self.size as f32;
+        let u = (sc / ma + 1.0) * 0.5 * size - 0.5;
+        let v = (tc / ma + 1.0) * 0.5 * size - 0.5;
+        let (x0, y0) = (u.floor(), v.floor());
+        let (fx, fy) = (u - x0, v - y0);
+
+        let texel = |x: f32, y: f32| {
+            let x = (x.max(0.0) as u32).min(self.size - 1);
+            let y = (y.max(0.0) as u32).min(self.size - 1);
+            self.faces[face][(y * self.size + x) as usize]
+        };
+        let top = texel(x0, y0).lerp(texel(x0 + 1.0, y0), fx);
+        let bottom = texel(x0, y0 + 1.0).lerp(texel(x0 + 1.0, y0 + 1.0), fx);
+        top.lerp(bottom, fy)
+    }
+}
+
+/// Where a ray ended up, see `Trace` in shader.wgsl.
+struct Trace {
+    direction: Vec3,
+    color: Vec3,
+    transmittance: f32,
+}
+
+impl Trace {
+    fn captured(direction: Vec3) -> Self {
+        Self {
+            direction,
+            color: Vec3::ZERO,
+            transmittance: 0.0,
+        }
+    }
+}
+
+/// The `BlackHole` uniforms, plus the blackbody lookup texture as floats.
+struct Tracer {
+    mass: f32,
+    disk_inner_radius: f32,
+    disk_outer_radius: f32,
+    tolerance: f32,
+    max_steps: u32,
+    spin: f32,
+    inclination: f32,
+    metric: Metric,
+    accretion_rate: f32,
+    blackbody: Vec<Vec3>,
+}
+
+impl Tracer {
+    fn new(black_hole: &BlackHoleConfig) -> Self {
+        Self {
+            mass: black_hole.mass,
+            disk_inner_radius: black_hole.disk_inner_radius(),
+            disk_outer_radius: black_hole.disk_outer_radius,
+            tolerance: black_hole.tolerance,
+            max_steps: black_hole.max_steps,
+            spin: black_hole.spin,
+            inclination: black_hole.inclination.to_radians(),
+            metric: black_hole.metric,
+            accretion_rate: black_hole.accretion_rate,
+            blackbody: (0..blackbody::LUT_SIZE)
+                .map(|i| Vec3::from(blackbody::blackbody_rgb(blackbody::lut_temperature(i))))
+                .collect(),
+        }
+    }
+
+    /// `fs_universe` without the sRGB encoding.
+    fn shade(&self, camera_position: Vec3, direction: Vec3, sky: &CubeMap) -> Vec3 {
+        let origin = self.to_hole_frame(camera_position);
+        let direction = self.to_hole_frame(direction);
+        let trace = match self.metric {
+            Metric::Schwarzschild => self.trace_schwarzschild(origin, direction),
+            Metric::Kerr => self.trace_kerr(origin, direction),
+        };
+        trace.color + trace.transmittance * sky.sample(self.to_world_frame(trace.direction))
+    }
+
+    fn to_hole_frame(&self, v: Vec3) -> Vec3 {
+        let (s, c) = self.inclination.sin_cos();
+        Vec3::new(v.x, c * v.y + s * v.z, c * v.z - s * v.y)
+    }
+
+    fn to_world_frame(&self, v: Vec3) -> Vec3 {
+        let (s, c) = self.inclination.sin_cos();
+        Vec3::new(v.x, c * v.y - s * v.z, c * v.z + s * v.y)
+    }
+
+    fn on_disk(&self, radius: f32) -> bool {
+        radius >= self.disk_inner_radius && radius <= self.disk_outer_radius
+    }
+
+    fn disk_temperature(&self, radius: f32) -> f32 {
+        let x = self.mass / radius;
+        let t4 = self.accretion_rate * x * x * x * (1.0 - (self.disk_inner_radius / radius).sqrt());
+        DISK_TEMPERATURE * t4.max(0.0).sqrt().sqrt()
+    }
+
+    fn blackbody_color(&self, kelvin: f32) -> Vec3 {
+        let t = (kelvin / blackbody::LUT_MIN_TEMPERATURE).ln()
+            / (blackbody::LUT_MAX_TEMPERATURE / blackbody::LUT_MIN_TEMPERATURE).ln();
+        // Linear filtering between the texels of the lookup texture
+        let texel = t.clamp(0.0, 1.0) * (self.blackbody.len() - 1) as f32;
+        let i = (texel as usize).min(self.blackbody.len() - 2);
+        let rgb = self.blackbody[i].lerp(self.blackbody[i + 1], texel - i as f32);
+        rgb / rgb.dot(Vec3::new(0.2126, 0.7152, 0.0722))
+    }
+
+    fn disk_emission(&self, radius: f32, redshift: f32) -> Vec3 {
+        let temperature = redshift * self.disk_temperature(radius);
+        let brightness = (temperature / EXPOSURE_TEMPERATURE).powi(4);
+        Vec3::ONE - (-self.blackbody_color(temperature) * brightness).exp()
+    }
+
+    fn next_step(&self, step: f32, error: f32, r: f32) -> f32 {
+        let scale = (0.9 * (self.tolerance / error.max(1e-30)).powf(1.0 / 3.0)).clamp(0.2, 5.0);
+        (step * scale).min(0.5 * r)
+    }
+
+    fn schwarzschild_acceleration(&self, pos: Vec3, h2: f32) -> Vec3 {
+        let r2 = pos.length_squared().max(1e-12);
+        -3.0 * self.mass * h2 * pos / (r2 * r2 * r2.sqrt())
+    }
+
+    fn trace_schwarzschild(&self, origin: Vec3, direction: Vec3) -> Trace {
+        let horizon = 2.0 * self.mass;
+        let escape_radius = (ESCAPE_RADIUS * self.mass).max(origin.length());
+
+        let mut pos = origin;
+        let mut vel = direction;
+        let h2 = pos.cross(vel).length_squared();
+        let angular_momentum = -pos.cross(vel).y;
+        let mut acc = self.schwarzschild_acceleration(pos, h2);
+        let mut step = 0.1 * pos.length();
+
+        for _ in 0..self.max_steps {
+            let r = pos.length();
+            if r < horizon {
+                return Trace::captured(direction);
+            }
+            if r >= escape_radius && pos.dot(vel) > 0.0 {
+                return Trace {
+                    direction: vel.normalize(),
+                    color: Vec3::ZERO,
+                    transmittance: 1.0,
+                };
+            }
+
+            let vel2 = vel + 0.5 * step * acc;
+            let acc2 = self.schwarzschild_acceleration(pos + 0.5 * step * vel, h2);
+            let vel3 = vel + 0.75 * step * acc2;
+            let acc3 = self.schwarzschild_acceleration(pos + 0.75 * step * vel2, h2);
+            let next_pos = pos + step * (2.0 / 9.0 * vel + 1.0 / 3.0 * vel2 + 4.0 / 9.0 * vel3);
+            let next_vel = vel + step * (2.0 / 9.0 * acc + 1.0 / 3.0 * acc2 + 4.0 / 9.0 * acc3);
+            let next_acc = self.schwarzschild_acceleration(next_pos, h2);
+
+            let pos_error = step
+                * (-5.0 / 72.0 * vel + 1.0 / 12.0 * vel2 + 1.0 / 9.0 * vel3 - 1.0 / 8.0 * next_vel);
+            let vel_error = step
+                * (-5.0 / 72.0 * acc + 1.0 / 12.0 * acc2 + 1.0 / 9.0 * acc3 - 1.0 / 8.0 * next_acc);
+            let error = (pos_error.length() / r).max(vel_error.length() / vel.length());
+
+            if error <= self.tolerance {
+                if pos.y * next_pos.y <= 0.0 && pos.y != next_pos.y {
+                    let hit = pos.lerp(next_pos, pos.y / (pos.y - next_pos.y));
+                    let disk_radius = Vec2::new(hit.x, hit.z).length();
+                    if self.on_disk(disk_radius) {
+                        let m = self.mass;
+                        let omega = (m / (disk_radius * disk_radius * disk_radius)).sqrt();
+                        let ut = 1.0 / (1.0 - 3.0 * m / disk_radius).sqrt();
+                        let redshift = 1.0 / (ut * (1.0 - omega * angular_momentum));
+                        return Trace {
+                            direction,
+                            color: self.disk_emission(disk_radius, redshift),
+                            transmittance: 0.0,
+                        };
+                    }
+                }
+
+                pos = next_pos;
+                vel = next_vel;
+                acc = next_acc;
+            }
+            step = self.next_step(step, error, r);
+        }
+
+        Trace::captured(direction)
+    }
+
+    fn kerr_derivative(&self, state: KerrState, angular_momentum: f32) -> KerrState {
+        let m = self.mass;
+        let a = self.spin * m;
+        let l = angular_momentum;
+        let r = state.q.x;
+        let sin_theta = state.q.y.sin().max(1e-4);
+        let cos_theta = state.q.y.cos();
+
+        let sigma = r * r + a * a * cos_theta * cos_theta;
+        let delta = r * r - 2.0 * m * r + a * a;
+        let p = r * r + a * a - a * l;
+
+        let q = -Vec3::new(
+            delta * state.p.x,
+            state.p.y,
+            a * p / delta + l / (sin_theta * sin_theta) - a,
+        ) / sigma;
+
+        let d_delta = 2.0 * r - 2.0 * m;
+        let dh_dr = d_delta * state.p.x * state.p.x - 4.0 * r * p / delta
+            + p * p * d_delta / (delta * delta);
+        let dh_dtheta = -2.0 * l * l * cos_theta / (sin_theta * sin_theta * sin_theta)
+            + 2.0 * a * a * sin_theta * cos_theta;
+        KerrState {
+            q,
+            p: Vec2::new(dh_dr, dh_dtheta) / (2.0 * sigma),
+        }
+    }
+
+    fn trace_kerr(&self, origin: Vec3, direction: Vec3) -> Trace {
+        let m = self.mass;
+        let a = self.spin * m;
+        let horizon = m + (m * m - a * a).max(0.0).sqrt();
+
+        let w = origin.length_squared() - a * a;
+        let r = (0.5 * (w + (w * w + 4.0 * a * a * origin.y * origin.y).sqrt())).sqrt();
+        if r <= horizon * 1.01 {
+            return Trace::captured(direction);
+        }
+        let theta = (origin.y / r).clamp(-1.0, 1.0).acos();
+        let phi = origin.x.atan2(origin.z);
+        let escape_radius = (ESCAPE_RADIUS * m).max(r);
+
+        let n = spherical_basis(theta, phi).transpose() * -direction;
+        let sin_theta = theta.sin().max(1e-4);
+        let sigma = r * r + a * a * theta.cos() * theta.cos();
+        let delta = r * r - 2.0 * m * r + a * a;
+        let big_a = (r * r + a * a) * (r * r + a * a) - a * a * delta * sin_theta * sin_theta;
+        let omega = 2.0 * m * a * r / big_a;
+        let lapse = (sigma * delta / big_a).sqrt();
+        let l_local = (big_a / sigma).sqrt() * sin_theta * n.z;
+        let energy = lapse + omega * l_local;
+        let angular_momentum = l_local / energy;
+
+        let mut state = KerrState {
+            q: Vec3::new(r, theta, phi),
+            p: Vec2::new((sigma / delta).sqrt() * n.x, sigma.sqrt() * n.y) / energy,
+        };
+        let mut d = self.kerr_derivative(state, angular_momentum);
+        let mut step = 0.1 * r;
+
+        for _ in 0..self.max_steps {
+            let r = state.q.x;
+            if r < horizon * 1.01 {
+                return Trace::captured(direction);
+            }
+            if r >= escape_radius && d.q.x > 0.0 {
+                let basis = spherical_basis(state.q.y, state.q.z);
+                return Trace {
+                    direction: (basis * (d.q * Vec3::new(1.0, r, r * state.q.y.sin()))).normalize(),
+                    color: Vec3::ZERO,
+                    transmittance: 1.0,
+                };
+            }
+
+            let d2 = self.kerr_derivative(state.add(d, 0.5 * step), angular_momentum);
+            let d3 = self.kerr_derivative(state.add(d2, 0.75 * step), angular_momentum);
+            let next = KerrState {
+                q: state.q + step * (2.0 / 9.0 * d.q + 1.0 / 3.0 * d2.q + 4.0 / 9.0 * d3.q),
+                p: state.p + step * (2.0 / 9.0 * d.p + 1.0 / 3.0 * d2.p + 4.0 / 9.0 * d3.p),
+            };
+            let next_d = self.kerr_derivative(next, angular_momentum);
+
+            let q_error = step
+                * (-5.0 / 72.0 * d.q + 1.0 / 12.0 * d2.q + 1.0 / 9.0 * d3.q - 1.0 / 8.0 * next_d.q);
+            let p_error = step
+                * (-5.0 / 72.0 * d.p + 1.0 / 12.0 * d2.p + 1.0 / 9.0 * d3.p - 1.0 / 8.0 * next_d.p);
+            let error = (q_error.x.abs() / r)
+                .max(q_error.y.abs().max(q_error.z.abs() * state.q.y.sin()))
+                .max(p_error.x.abs().max(p_error.y.abs() / r));
+
+            if error <= self.tolerance {
+                let below = state.q.y - HALF_PI;
+                let next_below = next.q.y - HALF_PI;
+                if below * next_below <= 0.0 && below != next_below {
+                    let t = below / (below - next_below);
+                    let disk_radius = state.q.x + (next.q.x - state.q.x) * t;
+                    if self.on_disk(disk_radius) {
+                        let sqrt_m = m.sqrt();
+                        let r32 = disk_radius * disk_radius.sqrt();
+                        let omega = sqrt_m / (r32 + a * sqrt_m);
+                        let ut = (r32 + a * sqrt_m)
+                            / (r32.sqrt()
+                                * (r32 - 3.0 * m * disk_radius.sqrt() + 2.0 * a * sqrt_m).sqrt());
+                        let redshift = 1.0 / (ut * (1.0 - omega * angular_momentum));
+                        return Trace {
+                            direction,
+                            color: self.disk_emission(disk_radius, redshift),
+                            transmittance: 0.0,
+                        };
+                    }
+                }
+
+                state = next;
+                d = next_d;
+            }
+            step = self.next_step(step, error, r);
+        }
+
+        Trace::captured(direction)
+    }
+}
+
+/// Boyer-Lindquist coordinates (r, θ, φ) and momenta (p_r, p_θ) of a photon, see `KerrState` in
+/// shader.wgsl.
+#[derive(Clone, Copy)]
+struct KerrState {
+    q: Vec3,
+    p: Vec2,
+}
+
+impl KerrState {
+    fn add(self, d: KerrState, step: f32) -> KerrState {
+        KerrState {
+            q: self.q + step * d.q,
+            p: self.p + step * d.p,
+        }
+    }
+}
+
+fn spherical_basis(theta: f32, phi: f32) -> Mat3 {
+    let (sin_theta, cos_theta) = theta.sin_cos();
+    let (sin_phi, cos_phi) = phi.sin_cos();
+    Mat3::from_cols(
+        Vec3::new(sin_theta * sin_phi, cos_theta, sin_theta * cos_phi),
+        Vec3::new(cos_theta * sin_phi, -sin_theta, cos_theta * cos_phi),
+        Vec3::new(cos_phi, 0.0, -sin_phi),
+    )
+}
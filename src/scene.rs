@@ -1,21 +1,109 @@
-use wgpu::{util::DeviceExt, AstcBlock, AstcChannel};
+use std::{ops::Range, path::Path};
 
-use crate::{camera::Camera, create_sphere_entity, generate_sphere, Cli, Entity, Vertex};
+use wgpu::util::DeviceExt;
+use winit::{
+    event::{ElementState, KeyEvent, WindowEvent},
+    keyboard::{KeyCode, PhysicalKey},
+};
+
+#[cfg(not(target_arch = "wasm32"))]
+use crate::hot_reload::{ShaderWatcher, SHADER_PATH};
+
+use crate::{
+    blackbody,
+    camera::{Camera, CameraMode},
+    clock::FrameTime,
+    config::{BlackHoleConfig, Config, LightingConfig, MaterialConfig, MeshConfig, TrailConfig},
+    create_model_entities, create_sphere_entity, generate_cube_sphere, generate_icosphere,
+    generate_sphere,
+    lines::{push_line, LinePoint, LineVertex, Trail},
+    nbody::{Body, Prediction, Simulation},
+    orbit::KeplerOrbit,
+    scene_graph::{NodeId, SceneGraph},
+    skybox::{self, SkyboxSource},
+    texture::{self, FLAT_NORMAL},
+    uniforms::{Data, EntityData},
+    Cli, Entity, TexturedVertex, Transform, Vertex,
+};
+
+// Switches between the Schwarzschild and Kerr metric.
+const METRIC_TOGGLE_KEY: KeyCode = KeyCode::KeyM;
+// Real seconds between log lines about how well the N-body simulation conserves energy.
+const DIAGNOSTICS_INTERVAL: f32 = 10.0;
+// Points along the longest predicted path.
+const PREDICTION_SAMPLES: f64 = 200.0;
+// Predicted paths are fainter than the trails, and fade out further ahead.
+const PREDICTION_OPACITY: f32 = 0.5;
+// Line vertices the buffer has room for at first, it grows when trails need more.
+const INITIAL_LINE_VERTICES: u64 = 4096;
+
+// Node moved along an orbit.
+struct Orbiter {
+    orbit: KeplerOrbit,
+    node: NodeId,
+}
+
+// Entities turning around their own y axis, from an entity config with a rotation period.
+struct Spinner {
+    rotation_period: f64,
+    entities: Range<usize>,
+}
+
+// Trail and predicted path of a node, from an entity config with a trail.
+struct TrailLine {
+    node: NodeId,
+    // Simulation body predicting the path.
+    body: Option<usize>,
+    trail: Trail,
+    config: TrailConfig,
+}
 
 pub struct Scene {
     camera: Camera,
+    black_hole: BlackHoleConfig,
+    lighting: LightingConfig,
     universe_pipeline: wgpu::RenderPipeline,
     entity_pipeline: wgpu::RenderPipeline,
+    textured_entity_pipeline: wgpu::RenderPipeline,
+    line_pipeline: wgpu::RenderPipeline,
+    universe_pipeline_layout: wgpu::PipelineLayout,
+    entity_pipeline_layout: wgpu::PipelineLayout,
+    textured_entity_pipeline_layout: wgpu::PipelineLayout,
+    color_format: wgpu::TextureFormat,
+    #[cfg(not(target_arch = "wasm32"))]
+    shader_watcher: Option<ShaderWatcher>,
     bind_group: wgpu::BindGroup,
     uniform_buf: wgpu::Buffer,
+    entity_bind_group: wgpu::BindGroup,
+    entity_uniform_buf: wgpu::Buffer,
+    entity_uniform_stride: wgpu::BufferAddress,
     entities: Vec<Entity>,
+    graph: SceneGraph,
+    // Node the camera moves along with, and where that node was last frame.
+    camera_anchor: Option<(NodeId, glam::Vec3)>,
+    simulation: Simulation,
+    // Simulation body moving each node, which is at the top of the hierarchy.
+    bodies: Vec<(usize, NodeId)>,
+    orbiters: Vec<Orbiter>,
+    spinners: Vec<Spinner>,
+    trail_lines: Vec<TrailLine>,
+    prediction: Option<Prediction>,
+    // Positions of each body along the longest predicted path, `PREDICTION_SAMPLES` apart.
+    predictions: Vec<Vec<glam::Vec3>>,
+    line_vertex_buf: wgpu::Buffer,
+    line_vertex_capacity: u64,
+    line_vertex_count: u32,
+    // Real seconds since the drift of the simulation was last logged.
+    diagnostics_timer: f32,
     depth_view: wgpu::TextureView,
     staging_belt: wgpu::util::StagingBelt,
-    rotation_angle: f32,
 }
 
 impl Scene {
     const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth24Plus;
+    const UNIFORM_SIZE: wgpu::BufferSize = wgpu::BufferSize::new(size_of::<Data>() as u64).unwrap();
+    const ENTITY_UNIFORM_SIZE: wgpu::BufferSize =
+        wgpu::BufferSize::new(size_of::<EntityData>() as u64).unwrap();
 
     fn create_depth_texture(
         config: &wgpu::SurfaceConfiguration,
@@ -38,6 +126,429 @@ impl Scene {
 
         depth_texture.create_view(&wgpu::TextureViewDescriptor::default())
     }
+
+    /// Size of one entity's slot in the entity uniform buffer, padded to the dynamic offset alignment.
+    fn entity_uniform_stride(device: &wgpu::Device) -> wgpu::BufferAddress {
+        wgpu::util::align_to(
+            Self::ENTITY_UNIFORM_SIZE.get(),
+            device.limits().min_uniform_buffer_offset_alignment as wgpu::BufferAddress,
+        )
+    }
+
+    fn create_pipelines(
+        device: &wgpu::Device,
+        shader: &wgpu::ShaderModule,
+        universe_pipeline_layout: &wgpu::PipelineLayout,
+        entity_pipeline_layout: &wgpu::PipelineLayout,
+        textured_entity_pipeline_layout: &wgpu::PipelineLayout,
+        color_format: wgpu::TextureFormat,
+    ) -> (
+        wgpu::RenderPipeline,
+        wgpu::RenderPipeline,
+        wgpu::RenderPipeline,
+        wgpu::RenderPipeline,
+    ) {
+        let universe_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Universe"),
+            layout: Some(universe_pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: shader,
+                entry_point: Some("vs_universe"),
+                compilation_options: Default::default(),
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: shader,
+                entry_point: Some("fs_universe"),
+                compilation_options: Default::default(),
+                targets: &[Some(color_format.into())],
+            }),
+            primitive: wgpu::PrimitiveState {
+                front_face: wgpu::FrontFace::Cw,
+                ..Default::default()
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: Self::DEPTH_FORMAT,
+                depth_write_enabled: false,
+                depth_compare: wgpu::CompareFunction::LessEqual,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+            cache: None,
+        });
+
+        let entity_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Entity"),
+            layout: Some(entity_pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: shader,
+                entry_point: Some("vs_entity"),
+                compilation_options: Default::default(),
+                buffers: &[wgpu::VertexBufferLayout {
+                    array_stride: size_of::<Vertex>() as wgpu::BufferAddress,
+                    step_mode: wgpu::VertexStepMode::Vertex,
+                    attributes: &wgpu::vertex_attr_array![0 => Float32x3, 1 => Float32x3],
+                }],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: shader,
+                entry_point: Some("fs_entity"),
+                compilation_options: Default::default(),
+                targets: &[Some(color_format.into())],
+            }),
+            primitive: wgpu::PrimitiveState {
+                front_face: wgpu::FrontFace::Cw,
+                ..Default::default()
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: Self::DEPTH_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: wgpu::CompareFunction::LessEqual,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+            cache: None,
+        });
+
+        let textured_entity_pipeline =
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some("Textured Entity"),
+                layout: Some(textured_entity_pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: shader,
+                    entry_point: Some("vs_textured_entity"),
+                    compilation_options: Default::default(),
+                    buffers: &[wgpu::VertexBufferLayout {
+                        array_stride: size_of::<TexturedVertex>() as wgpu::BufferAddress,
+                        step_mode: wgpu::VertexStepMode::Vertex,
+                        attributes: &wgpu::vertex_attr_array![
+                            0 => Float32x3,
+                            1 => Float32x3,
+                            2 => Float32x2,
+                            3 => Float32x4,
+                        ],
+                    }],
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: shader,
+                    entry_point: Some("fs_textured_entity"),
+                    compilation_options: Default::default(),
+                    targets: &[Some(color_format.into())],
+                }),
+                primitive: wgpu::PrimitiveState {
+                    front_face: wgpu::FrontFace::Cw,
+                    ..Default::default()
+                },
+                depth_stencil: Some(wgpu::DepthStencilState {
+                    format: Self::DEPTH_FORMAT,
+                    depth_write_enabled: true,
+                    depth_compare: wgpu::CompareFunction::LessEqual,
+                    stencil: wgpu::StencilState::default(),
+                    bias: wgpu::DepthBiasState::default(),
+                }),
+                multisample: wgpu::MultisampleState::default(),
+                multiview: None,
+                cache: None,
+            });
+
+        // Lines only need the camera from group 0, like the universe
+        let line_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Line"),
+            layout: Some(universe_pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: shader,
+                entry_point: Some("vs_line"),
+                compilation_options: Default::default(),
+                buffers: &[wgpu::VertexBufferLayout {
+                    array_stride: size_of::<LineVertex>() as wgpu::BufferAddress,
+                    step_mode: wgpu::VertexStepMode::Vertex,
+                    attributes: &LineVertex::ATTRIBUTES,
+                }],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: shader,
+                entry_point: Some("fs_line"),
+                compilation_options: Default::default(),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: color_format,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: Self::DEPTH_FORMAT,
+                depth_write_enabled: false,
+                depth_compare: wgpu::CompareFunction::LessEqual,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+            cache: None,
+        });
+
+        (
+            universe_pipeline,
+            entity_pipeline,
+            textured_entity_pipeline,
+            line_pipeline,
+        )
+    }
+
+    /// Layout of group 2, the albedo and normal map of a textured entity.
+    fn create_texture_bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+        let texture_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                multisampled: false,
+                view_dimension: wgpu::TextureViewDimension::D2,
+            },
+            count: None,
+        };
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Entity Textures"),
+            entries: &[
+                texture_entry(0),
+                texture_entry(1),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        })
+    }
+
+    /// Load the albedo and normal map of a material, standing in neutral texels for a missing one.
+    fn load_material_textures(
+        material: &MaterialConfig,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> Result<[wgpu::TextureView; 2], anyhow::Error> {
+        let load = |path: Option<&Path>, srgb, fallback, label| {
+            let texture = match path {
+                Some(path) => texture::load_texture(path, srgb, device, queue)?,
+                None => texture::create_solid_texture(fallback, srgb, label, device, queue),
+            };
+            Ok::<_, anyhow::Error>(texture.create_view(&wgpu::TextureViewDescriptor::default()))
+        };
+        Ok([
+            load(
+                material.albedo_map.as_deref(),
+                true,
+                [255; 4],
+                "White Albedo",
+            )?,
+            load(
+                material.normal_map.as_deref(),
+                false,
+                FLAT_NORMAL,
+                "Flat Normal Map",
+            )?,
+        ])
+    }
+
+    fn create_line_vertex_buffer(device: &wgpu::Device, capacity: u64) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Lines"),
+            size: capacity * size_of::<LineVertex>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    /// Record where the nodes with a trail are now, and build the lines to draw this frame.
+    fn update_lines(&mut self, time: FrameTime) -> Vec<LineVertex> {
+        let prediction = self
+            .trail_lines
+            .iter()
+            .map(|line| line.config.prediction)
+            .fold(0.0, f64::max);
+        let interval = prediction / PREDICTION_SAMPLES;
+        if prediction > 0.0 {
+            // Running backwards, the paths show where the bodies came from. Paused, they keep
+            // pointing the way time last went.
+            let backwards = if time.sim != 0.0 {
+                time.sim < 0.0
+            } else {
+                self.prediction
+                    .as_ref()
+                    .is_some_and(|prediction| prediction.is_backwards())
+            };
+            let simulation = &self.simulation;
+            let cached = self
+                .prediction
+                .get_or_insert_with(|| Prediction::new(simulation, interval, backwards));
+            cached.update(simulation, prediction, interval, backwards);
+            self.predictions = cached
+                .paths(simulation)
+                .into_iter()
+                .map(|path| path.iter().map(|position| position.as_vec3()).collect())
+                .collect();
+        }
+
+        let mut vertices = Vec::new();
+        for line in self.trail_lines.iter_mut() {
+            let config = line.config;
+            let position = self.graph.world_position(line.node);
+            let color = glam::Vec3::from(config.color).extend(config.opacity);
+
+            if config.duration > 0.0 {
+                line.trail.record(time.sim_time, position);
+                let points = line.trail.points(time.sim_time, position, color);
+                push_line(&mut vertices, &points, config.width);
+            }
+
+            if let Some(path) = line
+                .body
+                .filter(|_| config.prediction > 0.0)
+                .and_then(|body| self.predictions.get(body))
+            {
+                let count = ((config.prediction / interval).round() as usize + 1).min(path.len());
+                let points: Vec<LinePoint> = path[..count]
+                    .iter()
+                    .enumerate()
+                    .map(|(i, &position)| {
+                        let fade = 1.0 - i as f32 / (count - 1).max(1) as f32;
+                        LinePoint {
+                            position,
+                            color: color
+                                * glam::Vec4::new(1.0, 1.0, 1.0, PREDICTION_OPACITY * fade),
+                        }
+                    })
+                    .collect();
+                push_line(&mut vertices, &points, config.width);
+            }
+        }
+        vertices
+    }
+
+    /// Advance the N-body simulation and move the nodes along with their bodies.
+    fn update_simulation(&mut self, time: FrameTime) {
+        if self.simulation.is_empty() {
+            return;
+        }
+        self.simulation.advance(time.sim as f64);
+
+        // Bodies move in world space, even if their nodes were reparented since
+        let bodies = self.simulation.bodies();
+        for &(index, node) in self.bodies.iter() {
+            self.graph
+                .set_world_position(node, bodies[index].position.as_vec3());
+        }
+
+        self.diagnostics_timer += time.real;
+        if self.diagnostics_timer >= DIAGNOSTICS_INTERVAL {
+            self.diagnostics_timer = 0.0;
+            let drift = self.simulation.drift_since_start();
+            log::info!(
+                "N-body drift: energy {:+.2e}, momentum {:.2e}, angular momentum {:.2e}",
+                drift.energy,
+                drift.momentum,
+                drift.angular_momentum
+            );
+        }
+    }
+
+    /// Move the nodes with an orbit to where they are at `sim_time`.
+    fn update_orbits(&mut self, sim_time: f64) {
+        for orbiter in &self.orbiters {
+            self.graph.local_mut(orbiter.node).position =
+                orbiter.orbit.position(sim_time).as_vec3();
+        }
+    }
+
+    /// Turn the entities with a rotation period to their angle at `sim_time`.
+    fn update_rotations(&mut self, sim_time: f64) {
+        for spinner in &self.spinners {
+            let angle = (std::f64::consts::TAU * sim_time / spinner.rotation_period)
+                .rem_euclid(std::f64::consts::TAU);
+            let spin = glam::Quat::from_rotation_y(angle as f32);
+            for entity in &mut self.entities[spinner.entities.clone()] {
+                entity.transform.rotation = spin;
+            }
+        }
+    }
+
+    /// Move the camera by as much as the node it is attached to moved since the last frame.
+    fn follow_camera_anchor(&mut self) {
+        if let Some((node, last_position)) = self.camera_anchor.as_mut() {
+            let position = self.graph.world_position(*node);
+            self.camera.follow(position - *last_position);
+            *last_position = position;
+        }
+    }
+
+    pub fn graph(&self) -> &SceneGraph {
+        &self.graph
+    }
+
+    /// Nodes can be moved and reparented here, the entities follow on the next frame. Nodes of
+    /// N-body entities keep following their bodies in world space, whatever their parent.
+    pub fn graph_mut(&mut self) -> &mut SceneGraph {
+        &mut self.graph
+    }
+
+    /// Make the camera move along with `node`, starting with the orbit target on it.
+    /// `None` detaches it.
+    pub fn attach_camera(&mut self, node: Option<NodeId>) {
+        self.camera_anchor = node.map(|node| {
+            let position = self.graph.world_position(node);
+            self.camera.target = position;
+            (node, position)
+        });
+    }
+
+    /// Rebuild the pipelines from new shader source. On errors the old pipelines stay in place.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn reload_shader(&mut self, source: &str, device: &wgpu::Device) {
+        // Without an error scope wgpu treats validation errors as fatal.
+        device.push_error_scope(wgpu::ErrorFilter::Validation);
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("shader.wgsl"),
+            source: wgpu::ShaderSource::Wgsl(source.into()),
+        });
+        let pipelines = Self::create_pipelines(
+            device,
+            &shader,
+            &self.universe_pipeline_layout,
+            &self.entity_pipeline_layout,
+            &self.textured_entity_pipeline_layout,
+            self.color_format,
+        );
+
+        match pollster::block_on(device.pop_error_scope()) {
+            Some(error) => {
+                // The description carries naga's diagnostic with the offending source line.
+                let message = match error {
+                    wgpu::Error::Validation { description, .. } => description,
+                    error => error.to_string(),
+                };
+                log::error!(
+                    "Failed to reload {}, keeping the old pipelines: {}",
+                    SHADER_PATH,
+                    message
+                );
+            }
+            None => {
+                (
+                    self.universe_pipeline,
+                    self.entity_pipeline,
+                    self.textured_entity_pipeline,
+                    self.line_pipeline,
+                ) = pipelines;
+                log::info!("Reloaded {}", SHADER_PATH);
+            }
+        }
+    }
 }
 
 impl crate::framework::Framework for Scene {
@@ -54,16 +565,147 @@ impl crate::framework::Framework for Scene {
         device: &wgpu::Device,
         queue: &wgpu::Queue,
     ) -> Result<Self, anyhow::Error> {
+        let scene_config = match &args.scene {
+            Some(path) => Config::load(path)?,
+            None => Config::from_cli(args)?,
+        };
+
         let mut entities = Vec::new();
+        let mut simulation = scene_config
+            .simulation
+            .create_simulation(&scene_config.black_hole);
+        let mut bodies = Vec::new();
+        let mut graph = SceneGraph::new();
+        let mut nodes = Vec::new();
+        let mut orbiters = Vec::new();
+        let mut spinners = Vec::new();
+        let mut trail_lines = Vec::new();
+        let texture_bind_group_layout = Self::create_texture_bind_group_layout(device);
+        // Repeat, so tiled texture coordinates and the seam of a sphere filter across the edge.
+        let material_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Material"),
+            address_mode_u: wgpu::AddressMode::Repeat,
+            address_mode_v: wgpu::AddressMode::Repeat,
+            address_mode_w: wgpu::AddressMode::Repeat,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
         {
-            let r = args.sphere_radius;
-            let stacks = args.sphere_stacks;
-            let slices = args.sphere_slices;
+            for (i, entity_config) in scene_config.entities.iter().enumerate() {
+                let textured = entity_config.material.is_textured();
+                // Untextured spheres leave out the texture coordinates and tangents
+                let sphere = |(vertices, indices): (Vec<TexturedVertex>, Vec<u32>)| {
+                    if textured {
+                        create_sphere_entity(device, vertices, indices)
+                    } else {
+                        let vertices: Vec<Vertex> =
+                            vertices.into_iter().map(Vertex::from).collect();
+                        create_sphere_entity(device, vertices, indices)
+                    }
+                };
+                let mut new_entities = match &entity_config.mesh {
+                    MeshConfig::Sphere {
+                        radius,
+                        stacks,
+                        slices,
+                    } => vec![sphere(generate_sphere(*radius, *stacks, *slices))?],
+                    MeshConfig::Icosphere {
+                        radius,
+                        subdivisions,
+                    } => vec![sphere(generate_icosphere(*radius, *subdivisions))?],
+                    MeshConfig::CubeSphere { radius, resolution } => {
+                        vec![sphere(generate_cube_sphere(*radius, *resolution))?]
+                    }
+                    MeshConfig::Obj { path } => create_model_entities(device, path, textured)?,
+                };
+                let name = entity_config.name.clone().unwrap_or_else(|| i.to_string());
+                log::info!("Adding entity {} with {} meshes", name, new_entities.len());
 
-            let (vertices, indices) = generate_sphere(r, stacks, slices);
-            let sphere_entity = create_sphere_entity(device, vertices, indices)?;
+                // The node carries the placement children inherit, the scale stays with the meshes.
+                let transform = entity_config.transform();
+                let node = graph.add_node(
+                    entity_config.name.clone(),
+                    None,
+                    Transform {
+                        scale: glam::Vec3::ONE,
+                        ..transform
+                    },
+                );
+                for entity in new_entities.iter_mut() {
+                    entity.node = Some(node);
+                    entity.transform = Transform {
+                        scale: transform.scale,
+                        ..Transform::IDENTITY
+                    };
+                    entity.material = (&entity_config.material).into();
+                }
+                if textured {
+                    let [albedo, normal_map] =
+                        Self::load_material_textures(&entity_config.material, device, queue)?;
+                    for entity in new_entities.iter_mut() {
+                        entity.textures =
+                            Some(device.create_bind_group(&wgpu::BindGroupDescriptor {
+                                layout: &texture_bind_group_layout,
+                                entries: &[
+                                    wgpu::BindGroupEntry {
+                                        binding: 0,
+                                        resource: wgpu::BindingResource::TextureView(&albedo),
+                                    },
+                                    wgpu::BindGroupEntry {
+                                        binding: 1,
+                                        resource: wgpu::BindingResource::TextureView(&normal_map),
+                                    },
+                                    wgpu::BindGroupEntry {
+                                        binding: 2,
+                                        resource: wgpu::BindingResource::Sampler(&material_sampler),
+                                    },
+                                ],
+                                label: Some("Entity Textures"),
+                            }));
+                    }
+                }
+                let body = entity_config.body.map(|body| {
+                    let index = simulation.add_body(Body {
+                        mass: body.mass,
+                        position: glam::Vec3::from(entity_config.position).as_dvec3(),
+                        velocity: body.velocity.into(),
+                    });
+                    bodies.push((index, node));
+                    index
+                });
+                if let Some(config) = entity_config.trail {
+                    trail_lines.push(TrailLine {
+                        node,
+                        body,
+                        trail: Trail::new(config.duration),
+                        config,
+                    });
+                }
+                if entity_config.orbit.is_some() {
+                    let orbit = scene_config.create_orbit(i).ok_or_else(|| {
+                        anyhow::anyhow!("Orbit of entity {} needs a period", name)
+                    })?;
+                    orbiters.push(Orbiter { orbit, node });
+                }
+                if let Some(rotation_period) = entity_config.rotation_period {
+                    spinners.push(Spinner {
+                        rotation_period,
+                        entities: entities.len()..entities.len() + new_entities.len(),
+                    });
+                }
+                nodes.push(node);
+                entities.append(&mut new_entities);
+            }
 
-            entities.push(sphere_entity);
+            // Parents may come after their children in the scene file
+            for (i, &node) in nodes.iter().enumerate() {
+                if let Some(parent) = scene_config.parent_index(i) {
+                    graph.reparent(node, Some(nodes[parent]))?;
+                }
+            }
+            graph.update();
 
             let bind_group_layout =
                 device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
@@ -75,7 +717,7 @@ impl crate::framework::Framework for Scene {
                             ty: wgpu::BindingType::Buffer {
                                 ty: wgpu::BufferBindingType::Uniform,
                                 has_dynamic_offset: false,
-                                min_binding_size: None,
+                                min_binding_size: Some(Self::UNIFORM_SIZE),
                             },
                             count: None,
                         },
@@ -95,114 +737,119 @@ impl crate::framework::Framework for Scene {
                             ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
                             count: None,
                         },
+                        wgpu::BindGroupLayoutEntry {
+                            binding: 3,
+                            visibility: wgpu::ShaderStages::FRAGMENT,
+                            ty: wgpu::BindingType::Texture {
+                                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                                multisampled: false,
+                                view_dimension: wgpu::TextureViewDimension::D2,
+                            },
+                            count: None,
+                        },
                     ],
                 });
 
             // Create the render pipeline
             let shader = device.create_shader_module(wgpu::include_wgsl!("shader.wgsl"));
 
-            let camera_distance = args.camera_distance.unwrap_or(150.0);
+            let camera = scene_config
+                .camera
+                .create_camera((config.width, config.height));
+            let black_hole = scene_config.black_hole;
+            let lighting = scene_config.lighting;
 
-            let camera = Camera {
-                screen_size: (config.width, config.height),
-                dist: camera_distance,
-                angle: -2.5,
+            let uniforms = Data {
+                camera: camera.to_uniform_data(),
+                black_hole: black_hole.to_uniform_data(),
+                lighting: lighting.to_uniform_data(),
             };
-            let raw_camera_data = camera.to_uniform_data();
-
-            let rotation_matrix = glam::Mat4::from_rotation_y(0.0);
-            let translation_matrix = glam::Mat4::from_translation(glam::Vec3::new(0.0, 2.0, 0.0));
-            // let model_matrix = rotation_matrix * translation_matrix;
-            let model_matrix = translation_matrix * rotation_matrix;
-
-            let raw_model_matrix = model_matrix.to_cols_array();
-
-            // Camera data: 52, Entity data: 16
-            let mut raw_uniforms = Vec::with_capacity(52 + 16);
-
-            raw_uniforms.extend_from_slice(&raw_camera_data);
-            raw_uniforms.extend_from_slice(&raw_model_matrix);
 
             let uniform_buf = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
                 label: Some("Buffer"),
-                contents: bytemuck::cast_slice(&raw_uniforms),
+                contents: bytemuck::bytes_of(&uniforms),
                 usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
             });
 
-            let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                label: None,
-                bind_group_layouts: &[&bind_group_layout],
-                push_constant_ranges: &[],
+            // Every entity gets its own slot in this buffer, selected with a dynamic offset per draw.
+            // Storage buffers would be simpler, but the WebGL2 limits don't have any.
+            let entity_uniform_stride = Self::entity_uniform_stride(device);
+            let entity_uniform_buf = device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("Entity Buffer"),
+                size: entity_uniform_stride * entities.len().max(1) as wgpu::BufferAddress,
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
             });
 
-            // Create the render pipelines
-            let universe_pipeline =
-                device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-                    label: Some("Universe"),
-                    layout: Some(&pipeline_layout),
-                    vertex: wgpu::VertexState {
-                        module: &shader,
-                        entry_point: Some("vs_universe"),
-                        compilation_options: Default::default(),
-                        buffers: &[],
-                    },
-                    fragment: Some(wgpu::FragmentState {
-                        module: &shader,
-                        entry_point: Some("fs_universe"),
-                        compilation_options: Default::default(),
-                        targets: &[Some(config.view_formats[0].into())],
-                    }),
-                    primitive: wgpu::PrimitiveState {
-                        front_face: wgpu::FrontFace::Cw,
-                        ..Default::default()
-                    },
-                    depth_stencil: Some(wgpu::DepthStencilState {
-                        format: Self::DEPTH_FORMAT,
-                        depth_write_enabled: false,
-                        depth_compare: wgpu::CompareFunction::LessEqual,
-                        stencil: wgpu::StencilState::default(),
-                        bias: wgpu::DepthBiasState::default(),
-                    }),
-                    multisample: wgpu::MultisampleState::default(),
-                    multiview: None,
-                    cache: None,
+            let entity_bind_group_layout =
+                device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                    label: Some("Entity"),
+                    entries: &[wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: true,
+                            min_binding_size: Some(Self::ENTITY_UNIFORM_SIZE),
+                        },
+                        count: None,
+                    }],
                 });
 
-            let entity_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            let entity_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                layout: &entity_bind_group_layout,
+                entries: &[wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                        buffer: &entity_uniform_buf,
+                        offset: 0,
+                        size: Some(Self::ENTITY_UNIFORM_SIZE),
+                    }),
+                }],
                 label: Some("Entity"),
-                layout: Some(&pipeline_layout),
-                vertex: wgpu::VertexState {
-                    module: &shader,
-                    entry_point: Some("vs_entity"),
-                    compilation_options: Default::default(),
-                    buffers: &[wgpu::VertexBufferLayout {
-                        array_stride: size_of::<Vertex>() as wgpu::BufferAddress,
-                        step_mode: wgpu::VertexStepMode::Vertex,
-                        attributes: &wgpu::vertex_attr_array![0 => Float32x3, 1 => Float32x3],
-                    }],
-                },
-                fragment: Some(wgpu::FragmentState {
-                    module: &shader,
-                    entry_point: Some("fs_entity"),
-                    compilation_options: Default::default(),
-                    targets: &[Some(config.view_formats[0].into())],
-                }),
-                primitive: wgpu::PrimitiveState {
-                    front_face: wgpu::FrontFace::Cw,
-                    ..Default::default()
-                },
-                depth_stencil: Some(wgpu::DepthStencilState {
-                    format: Self::DEPTH_FORMAT,
-                    depth_write_enabled: true,
-                    depth_compare: wgpu::CompareFunction::LessEqual,
-                    stencil: wgpu::StencilState::default(),
-                    bias: wgpu::DepthBiasState::default(),
-                }),
-                multisample: wgpu::MultisampleState::default(),
-                multiview: None,
-                cache: None,
             });
 
+            let universe_pipeline_layout =
+                device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                    label: None,
+                    bind_group_layouts: &[&bind_group_layout],
+                    push_constant_ranges: &[],
+                });
+
+            let entity_pipeline_layout =
+                device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                    label: None,
+                    bind_group_layouts: &[&bind_group_layout, &entity_bind_group_layout],
+                    push_constant_ranges: &[],
+                });
+
+            let textured_entity_pipeline_layout =
+                device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                    label: None,
+                    bind_group_layouts: &[
+                        &bind_group_layout,
+                        &entity_bind_group_layout,
+                        &texture_bind_group_layout,
+                    ],
+                    push_constant_ranges: &[],
+                });
+
+            let color_format = config.view_formats[0];
+            let (universe_pipeline, entity_pipeline, textured_entity_pipeline, line_pipeline) =
+                Self::create_pipelines(
+                    device,
+                    &shader,
+                    &universe_pipeline_layout,
+                    &entity_pipeline_layout,
+                    &textured_entity_pipeline_layout,
+                    color_format,
+                );
+
+            #[cfg(not(target_arch = "wasm32"))]
+            let shader_watcher = args
+                .watch_shader
+                .then(|| ShaderWatcher::new(Path::new(SHADER_PATH)));
+
             let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
                 label: None,
                 address_mode_u: wgpu::AddressMode::ClampToEdge,
@@ -214,82 +861,15 @@ impl crate::framework::Framework for Scene {
                 ..Default::default()
             });
 
-            let device_features = device.features();
-
-            let skybox_format =
-                if device_features.contains(wgpu::Features::TEXTURE_COMPRESSION_ASTC) {
-                    log::info!("Using astc");
-                    wgpu::TextureFormat::Astc {
-                        block: AstcBlock::B4x4,
-                        channel: AstcChannel::UnormSrgb,
-                    }
-                } else if device_features.contains(wgpu::Features::TEXTURE_COMPRESSION_ETC2) {
-                    log::info!("Using etc2");
-                    wgpu::TextureFormat::Etc2Rgb8A1UnormSrgb
-                // } else if device_features.contains(wgpu::Features::TEXTURE_COMPRESSION_BC) {
-                //     log::info!("Using bc7");
-                //     wgpu::TextureFormat::Bc7RgbaUnormSrgb
-                } else {
-                    log::info!("Using rgba8");
-                    wgpu::TextureFormat::Rgba8UnormSrgb
-                };
-
-            let image_size = args.image_size;
-
-            let size = wgpu::Extent3d {
-                width: image_size,
-                height: image_size,
-                depth_or_array_layers: 6,
-            };
-
-            let layer_size = wgpu::Extent3d {
-                depth_or_array_layers: 1,
-                ..size
-            };
-            let max_mips = layer_size.max_mips(wgpu::TextureDimension::D2);
-
-            log::info!(
-                "Copying {:?} skybox images of size {}, {}, 6 with {} mips to gpu",
-                skybox_format,
-                image_size,
-                image_size,
-                max_mips,
-            );
-
-            // Only supporting rgba8 files for now
-            if skybox_format != wgpu::TextureFormat::Rgba8UnormSrgb {
-                return Err(anyhow::anyhow!(
-                    "Unsupported texture type {:?} (only rgba8 is supported at the moment)",
-                    skybox_format
-                ));
-            }
-            let bytes = &include_bytes!("assets/images/skybox.ktx2");
-
-            let reader = ktx2::Reader::new(bytes)
-                .map_err(|e| anyhow::anyhow!("Failed to create KTX2 reader: {}", e))?;
-            let header = reader.header();
-
-            let mut image = Vec::with_capacity(reader.data().len());
-            for level in reader.levels() {
-                image.extend_from_slice(level);
-            }
-
-            let texture = device.create_texture_with_data(
-                queue,
-                &wgpu::TextureDescriptor {
-                    size,
-                    mip_level_count: header.level_count,
-                    sample_count: 1,
-                    dimension: wgpu::TextureDimension::D2,
-                    format: skybox_format,
-                    usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
-                    label: None,
-                    view_formats: &[],
-                },
-                // KTX2 stores mip levels in mip major order.
-                wgpu::util::TextureDataOrder::MipMajor,
-                &image,
-            );
+            // --skybox wins over the scene file.
+            let skybox_source = args
+                .skybox
+                .as_deref()
+                .or(scene_config.skybox.as_deref())
+                .map_or_else(SkyboxSource::find_default, |path| {
+                    Ok(SkyboxSource::from_path(path))
+                })?;
+            let texture = skybox::create_texture(&skybox_source, device, queue)?;
 
             let texture_view = texture.create_view(&wgpu::TextureViewDescriptor {
                 label: None,
@@ -297,6 +877,9 @@ impl crate::framework::Framework for Scene {
                 ..wgpu::TextureViewDescriptor::default()
             });
 
+            let blackbody_view = blackbody::create_lut_texture(device, queue)
+                .create_view(&wgpu::TextureViewDescriptor::default());
+
             let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
                 layout: &bind_group_layout,
                 entries: &[
@@ -312,23 +895,57 @@ impl crate::framework::Framework for Scene {
                         binding: 2,
                         resource: wgpu::BindingResource::Sampler(&sampler),
                     },
+                    wgpu::BindGroupEntry {
+                        binding: 3,
+                        resource: wgpu::BindingResource::TextureView(&blackbody_view),
+                    },
                 ],
                 label: None,
             });
 
             let depth_view = Self::create_depth_texture(config, device);
 
-            Ok(Scene {
+            let mut scene = Scene {
                 camera,
+                black_hole,
+                lighting,
                 universe_pipeline,
                 entity_pipeline,
+                textured_entity_pipeline,
+                line_pipeline,
+                universe_pipeline_layout,
+                entity_pipeline_layout,
+                textured_entity_pipeline_layout,
+                color_format,
+                #[cfg(not(target_arch = "wasm32"))]
+                shader_watcher,
                 bind_group,
                 uniform_buf,
+                entity_bind_group,
+                entity_uniform_buf,
+                entity_uniform_stride,
                 entities,
+                graph,
+                camera_anchor: None,
+                simulation,
+                bodies,
+                orbiters,
+                spinners,
+                trail_lines,
+                prediction: None,
+                predictions: Vec::new(),
+                line_vertex_buf: Self::create_line_vertex_buffer(device, INITIAL_LINE_VERTICES),
+                line_vertex_capacity: INITIAL_LINE_VERTICES,
+                line_vertex_count: 0,
+                diagnostics_timer: 0.0,
                 depth_view,
                 staging_belt: wgpu::util::StagingBelt::new(0x100),
-                rotation_angle: 0.0,
-            })
+            };
+            if let Some(attach) = &scene_config.camera.attach {
+                let node = scene_config.entity_index(attach).map(|index| nodes[index]);
+                scene.attach_camera(node);
+            }
+            Ok(scene)
         }
     }
 
@@ -342,51 +959,112 @@ impl crate::framework::Framework for Scene {
         self.camera.screen_size = (config.width, config.height);
     }
 
-    #[allow(clippy::single_match)]
-    fn update(&mut self, event: winit::event::WindowEvent) {
-        match event {
-            winit::event::WindowEvent::CursorMoved { position, .. } => {
-                let _norm_x = position.x as f32 / self.camera.screen_size.0 as f32 - 0.5;
-                let _norm_y = position.y as f32 / self.camera.screen_size.1 as f32 - 0.5;
-                // self.camera.angle = norm_x * 5.0;
-                // self.camera.angle_xz = (norm_y * 5.0).clamp(-1.5, 1.5);
-            }
-            _ => {}
+    fn update(&mut self, event: WindowEvent) {
+        if let WindowEvent::KeyboardInput {
+            event:
+                KeyEvent {
+                    physical_key: PhysicalKey::Code(METRIC_TOGGLE_KEY),
+                    state: ElementState::Pressed,
+                    repeat: false,
+                    ..
+                },
+            ..
+        } = event
+        {
+            self.black_hole.metric = self.black_hole.metric.toggle();
+            log::info!("Black hole metric {:?}", self.black_hole.metric);
         }
+        self.camera.process_event(&event);
     }
 
-    fn render(&mut self, view: &wgpu::TextureView, device: &wgpu::Device, queue: &wgpu::Queue) {
-        let mut encoder =
-            device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
-
-        // Update rotation angle for the sphere
-        self.rotation_angle += 0.0003;
+    fn mouse_motion(&mut self, delta: (f64, f64)) {
+        self.camera.process_mouse_motion(delta);
+    }
 
-        // Translation matrix to move the sphere to the origin
-        let rotation_matrix = glam::Mat4::from_rotation_y(self.rotation_angle);
+    fn cursor_grabbed(&self) -> bool {
+        self.camera.mode == CameraMode::FreeFly
+    }
 
-        let translation_matrix = glam::Mat4::from_translation(glam::Vec3::new(0.0, 2.0, 0.0));
-        // let model_matrix = rotation_matrix * translation_matrix;
-        let model_matrix = translation_matrix * rotation_matrix;
-        let raw_model_matrix = model_matrix.to_cols_array();
+    fn render(
+        &mut self,
+        view: &wgpu::TextureView,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        time: FrameTime,
+    ) {
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(source) = self.shader_watcher.as_mut().and_then(ShaderWatcher::poll) {
+            self.reload_shader(&source, device);
+        }
 
-        let raw_camera_data = self.camera.to_uniform_data();
+        let mut encoder =
+            device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
 
-        // Camera data: 52, Entity data: 16
-        let mut raw_uniforms = Vec::with_capacity(52 + 16);
+        self.camera.update(time.real);
+        self.update_orbits(time.sim_time);
+        self.update_rotations(time.sim_time);
+        self.update_simulation(time);
+        self.graph.update();
+        self.follow_camera_anchor();
 
-        raw_uniforms.extend_from_slice(&raw_camera_data);
-        raw_uniforms.extend_from_slice(&raw_model_matrix);
+        let uniforms = Data {
+            camera: self.camera.to_uniform_data(),
+            black_hole: self.black_hole.to_uniform_data(),
+            lighting: self.lighting.to_uniform_data(),
+        };
 
         self.staging_belt
             .write_buffer(
                 &mut encoder,
                 &self.uniform_buf,
                 0,
-                wgpu::BufferSize::new((raw_uniforms.len() * 4) as wgpu::BufferAddress).unwrap(),
+                Self::UNIFORM_SIZE,
                 device,
             )
-            .copy_from_slice(bytemuck::cast_slice(&raw_uniforms));
+            .copy_from_slice(bytemuck::bytes_of(&uniforms));
+
+        if let Some(entity_data_size) = wgpu::BufferSize::new(
+            self.entity_uniform_stride * self.entities.len() as wgpu::BufferAddress,
+        ) {
+            let mut entity_data = self.staging_belt.write_buffer(
+                &mut encoder,
+                &self.entity_uniform_buf,
+                0,
+                entity_data_size,
+                device,
+            );
+            for (i, entity) in self.entities.iter().enumerate() {
+                let model = entity
+                    .node
+                    .map_or(glam::Mat4::IDENTITY, |node| self.graph.world_matrix(node))
+                    * entity.transform.to_matrix();
+                let normal = glam::Mat3::from_mat4(model).inverse().transpose();
+                let uniforms = EntityData {
+                    model: model.to_cols_array_2d(),
+                    normal: [normal.x_axis, normal.y_axis, normal.z_axis]
+                        .map(|column| column.extend(0.0).to_array()),
+                    color: entity.material.color.extend(1.0).to_array(),
+                };
+
+                let offset = i * self.entity_uniform_stride as usize;
+                entity_data[offset..offset + Self::ENTITY_UNIFORM_SIZE.get() as usize]
+                    .copy_from_slice(bytemuck::bytes_of(&uniforms));
+            }
+        }
+
+        let line_vertices = self.update_lines(time);
+        if line_vertices.len() as u64 > self.line_vertex_capacity {
+            self.line_vertex_capacity = (line_vertices.len() as u64).next_power_of_two();
+            self.line_vertex_buf =
+                Self::create_line_vertex_buffer(device, self.line_vertex_capacity);
+        }
+        let line_data: &[u8] = bytemuck::cast_slice(&line_vertices);
+        if let Some(size) = wgpu::BufferSize::new(line_data.len() as wgpu::BufferAddress) {
+            self.staging_belt
+                .write_buffer(&mut encoder, &self.line_vertex_buf, 0, size, device)
+                .copy_from_slice(line_data);
+        }
+        self.line_vertex_count = line_vertices.len() as u32;
 
         self.staging_belt.finish();
 
@@ -419,9 +1097,17 @@ impl crate::framework::Framework for Scene {
             });
 
             rpass.set_bind_group(0, &self.bind_group, &[]);
-            rpass.set_pipeline(&self.entity_pipeline);
 
-            for entity in self.entities.iter() {
+            for (i, entity) in self.entities.iter().enumerate() {
+                match &entity.textures {
+                    Some(textures) => {
+                        rpass.set_pipeline(&self.textured_entity_pipeline);
+                        rpass.set_bind_group(2, textures, &[]);
+                    }
+                    None => rpass.set_pipeline(&self.entity_pipeline),
+                }
+                let offset = i as wgpu::BufferAddress * self.entity_uniform_stride;
+                rpass.set_bind_group(1, &self.entity_bind_group, &[offset as wgpu::DynamicOffset]);
                 rpass.set_vertex_buffer(0, entity.vertex_buf.slice(..));
                 rpass.set_index_buffer(entity.index_buf.slice(..), wgpu::IndexFormat::Uint32);
                 rpass.draw_indexed(0..entity.vertex_count, 0, 0..1);
@@ -429,6 +1115,13 @@ impl crate::framework::Framework for Scene {
 
             rpass.set_pipeline(&self.universe_pipeline);
             rpass.draw(0..3, 0..1);
+
+            // Over the universe, but behind the entities
+            if self.line_vertex_count > 0 {
+                rpass.set_pipeline(&self.line_pipeline);
+                rpass.set_vertex_buffer(0, self.line_vertex_buf.slice(..));
+                rpass.draw(0..self.line_vertex_count, 0..1);
+            }
         }
 
         queue.submit(std::iter::once(encoder.finish()));
@@ -0,0 +1,168 @@
+use crate::Transform;
+
+/// Handle to a node in a [`SceneGraph`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct NodeId(usize);
+
+struct Node {
+    name: Option<String>,
+    parent: Option<NodeId>,
+    children: Vec<NodeId>,
+    local: Transform,
+    world: glam::Mat4,
+}
+
+/// Hierarchy of transforms. A node's world matrix is its parent's world matrix times its own
+/// local transform, so children move, turn and scale along with their parents.
+///
+/// World matrices are only recomputed by [`SceneGraph::update`], once per frame.
+#[derive(Default)]
+pub struct SceneGraph {
+    nodes: Vec<Node>,
+    roots: Vec<NodeId>,
+}
+
+impl SceneGraph {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a node below `parent`, or at the top of the hierarchy.
+    pub fn add_node(
+        &mut self,
+        name: Option<String>,
+        parent: Option<NodeId>,
+        local: Transform,
+    ) -> NodeId {
+        let id = NodeId(self.nodes.len());
+        let world = parent.map_or(glam::Mat4::IDENTITY, |parent| self.node(parent).world)
+            * local.to_matrix();
+        self.nodes.push(Node {
+            name,
+            parent,
+            children: Vec::new(),
+            local,
+            world,
+        });
+        self.siblings(parent).push(id);
+        id
+    }
+
+    /// The first node with this name.
+    pub fn find(&self, name: &str) -> Option<NodeId> {
+        self.nodes
+            .iter()
+            .position(|node| node.name.as_deref() == Some(name))
+            .map(NodeId)
+    }
+
+    pub fn name(&self, id: NodeId) -> Option<&str> {
+        self.node(id).name.as_deref()
+    }
+
+    pub fn parent(&self, id: NodeId) -> Option<NodeId> {
+        self.node(id).parent
+    }
+
+    pub fn children(&self, id: NodeId) -> &[NodeId] {
+        &self.node(id).children
+    }
+
+    /// Transform relative to the parent.
+    pub fn local(&self, id: NodeId) -> &Transform {
+        &self.node(id).local
+    }
+
+    pub fn local_mut(&mut self, id: NodeId) -> &mut Transform {
+        &mut self.nodes[id.0].local
+    }
+
+    /// World matrix as of the last [`SceneGraph::update`].
+    pub fn world_matrix(&self, id: NodeId) -> glam::Mat4 {
+        self.node(id).world
+    }
+
+    /// World position as of the last [`SceneGraph::update`].
+    pub fn world_position(&self, id: NodeId) -> glam::Vec3 {
+        self.node(id).world.w_axis.truncate()
+    }
+
+    /// Move `id` to `position` in the world, whatever its parent. Goes by the current local
+    /// transforms of its ancestors, so it holds before the next [`SceneGraph::update`].
+    pub fn set_world_position(&mut self, id: NodeId, position: glam::Vec3) {
+        let mut parent_world = glam::Mat4::IDENTITY;
+        let mut ancestor = self.node(id).parent;
+        while let Some(node) = ancestor {
+            parent_world = self.node(node).local.to_matrix() * parent_world;
+            ancestor = self.node(node).parent;
+        }
+        self.nodes[id.0].local.position = parent_world.inverse().transform_point3(position);
+    }
+
+    /// Move `id` below `parent`, or to the top of the hierarchy. The local transform stays the
+    /// same, so the node jumps along with its new parent.
+    pub fn reparent(&mut self, id: NodeId, parent: Option<NodeId>) -> Result<(), anyhow::Error> {
+        let mut ancestor = parent;
+        while let Some(node) = ancestor {
+            if node == id {
+                return Err(anyhow::anyhow!(
+                    "Can't move node {} below its own descendant",
+                    id.0
+                ));
+            }
+            ancestor = self.node(node).parent;
+        }
+
+        let old_parent = self.node(id).parent;
+        self.siblings(old_parent).retain(|&child| child != id);
+        self.siblings(parent).push(id);
+        self.nodes[id.0].parent = parent;
+        Ok(())
+    }
+
+    /// Like [`SceneGraph::reparent`], but changes the local transform so the node stays where it
+    /// is in the world. Shear from non-uniform scales up the hierarchy is lost.
+    pub fn reparent_in_place(
+        &mut self,
+        id: NodeId,
+        parent: Option<NodeId>,
+    ) -> Result<(), anyhow::Error> {
+        self.reparent(id, parent)?;
+        let parent_world = parent.map_or(glam::Mat4::IDENTITY, |parent| self.node(parent).world);
+        let (scale, rotation, position) =
+            (parent_world.inverse() * self.node(id).world).to_scale_rotation_translation();
+        self.nodes[id.0].local = Transform {
+            position,
+            rotation,
+            scale,
+        };
+        Ok(())
+    }
+
+    /// Recompute every world matrix from the local transforms, parents before their children.
+    pub fn update(&mut self) {
+        let mut stack: Vec<(NodeId, glam::Mat4)> = self
+            .roots
+            .iter()
+            .rev()
+            .map(|&root| (root, glam::Mat4::IDENTITY))
+            .collect();
+        while let Some((id, parent_world)) = stack.pop() {
+            let node = &mut self.nodes[id.0];
+            node.world = parent_world * node.local.to_matrix();
+            let world = node.world;
+            stack.extend(node.children.iter().rev().map(|&child| (child, world)));
+        }
+    }
+
+    fn node(&self, id: NodeId) -> &Node {
+        &self.nodes[id.0]
+    }
+
+    fn siblings(&mut self, parent: Option<NodeId>) -> &mut Vec<NodeId> {
+        match parent {
+            Some(parent) => &mut self.nodes[parent.0].children,
+            None => &mut self.roots,
+        }
+    }
+}
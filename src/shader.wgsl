@@ -1,25 +1,78 @@
 struct UniverseOutput {
     @builtin(position) position: vec4<f32>,
-    @location(0) uv: vec3<f32>,
+    // world space direction of the camera ray
+    @location(0) ray_dir: vec3<f32>,
 };
 
-struct Data {
+struct BlackHoleData {
+    // in scene units (G = c = 1), the event horizon is at twice the mass
+    mass: f32,
+    disk_inner_radius: f32,
+    disk_outer_radius: f32,
+    // accepted error per integration step, relative to the distance from the hole
+    tolerance: f32,
+    // integration steps before a ray counts as captured
+    max_steps: f32,
+    // Kerr spin parameter a, in units of the mass
+    spin: f32,
+    // tilt of the spin axis and disk from the y axis towards z, in radians
+    inclination: f32,
+    // 0 for Schwarzschild, 1 for Kerr
+    metric: f32,
+    // relative to the rate that heats the disk around a non-rotating hole to 5000 K
+    accretion_rate: f32,
+};
+
+struct LightingData {
+    // towards the light, normalized
+    direction: vec3<f32>,
+    // Phong exponent
+    shininess: f32,
+    color: vec3<f32>,
+    specular_strength: f32,
+    ambient_color: vec3<f32>,
+    // share of the skybox reflection in the surface color
+    reflectivity: f32,
+};
+
+struct CameraData {
     // from camera to screen
     proj: mat4x4<f32>,
     // from screen to camera
     proj_inv: mat4x4<f32>,
     // from world to camera
     view: mat4x4<f32>,
-    // camera position
-    cam_pos: vec4<f32>,
-    // transformation model
+    position: vec4<f32>,
+    // in pixels
+    screen_size: vec2<f32>,
+};
+
+// Each struct here has a twin in uniforms.rs, tests/uniforms.rs checks that their layouts agree.
+struct Data {
+    camera: CameraData,
+    // black hole at the origin
+    black_hole: BlackHoleData,
+    // light for the entities
+    lighting: LightingData,
+};
+
+struct EntityData {
+    // from entity to world
     model: mat4x4<f32>,
+    // inverse transpose of the model matrix, for normals
+    normal: mat3x3<f32>,
+    // surface tint
+    color: vec4<f32>,
 };
 
 @group(0)
 @binding(0)
 var<uniform> r_data: Data;
 
+@group(1)
+@binding(0)
+var<uniform> r_entity: EntityData;
+
 @vertex
 fn vs_universe(@builtin(vertex_index) vertex_index: u32) -> UniverseOutput {
     // Generate a full-screen triangle
@@ -31,15 +84,11 @@ fn vs_universe(@builtin(vertex_index) vertex_index: u32) -> UniverseOutput {
     var result: UniverseOutput;
     result.position = vec4<f32>(pos, 0.9999, 1.0);
 
-    var skybox_view = r_data.view;
-    skybox_view[3] = vec4<f32>(0.0, 0.0, 0.0, 1.0);
-
-    result.uv = (skybox_view * vec4<f32>(pos, 1.0, 0.0)).xyz;
-
-    // Apply lensing effect near the black hole
-    let dist = length(result.uv);
-    let lens_factor = 1.0 / (dist * 0.8 + 0.5);
-    result.uv = result.uv * lens_factor;
+    // Point on the far plane in camera space
+    let far = r_data.camera.proj_inv * vec4<f32>(pos, 1.0, 1.0);
+    // The view matrix only rotates and translates, so the transpose undoes its rotation
+    let view_rotation = mat3x3<f32>(r_data.camera.view[0].xyz, r_data.camera.view[1].xyz, r_data.camera.view[2].xyz);
+    result.ray_dir = transpose(view_rotation) * (far.xyz / far.w);
 
     return result;
 }
@@ -58,11 +107,11 @@ fn vs_entity(
 ) -> EntityOutput {
     var result: EntityOutput;
 
-    let world_pos = r_data.model * vec4<f32>(pos, 1.0);
+    let world_pos = r_entity.model * vec4<f32>(pos, 1.0);
     result.world_position = world_pos;
-    result.position = r_data.proj * r_data.view * world_pos;
-    result.normal = normalize((r_data.model * vec4<f32>(normal, 0.0)).xyz);
-    result.view = (r_data.view * world_pos).xyz;
+    result.position = r_data.camera.proj * r_data.camera.view * world_pos;
+    result.normal = normalize(r_entity.normal * normal);
+    result.view = (r_data.camera.view * world_pos).xyz;
     return result;
 }
 
@@ -74,84 +123,461 @@ var r_texture: texture_cube<f32>;
 @binding(2)
 var r_sampler: sampler;
 
-@fragment
-fn fs_universe(vertex: UniverseOutput) -> @location(0) vec4<f32> {
-    return textureSample(r_texture, r_sampler, vertex.uv);
+@group(0)
+@binding(3)
+var r_blackbody: texture_2d<f32>;
+
+// reference.rs traces the same model on the CPU to test against, keep the two in sync.
+
+// Rays beyond this many masses hardly bend anymore and count as escaped.
+const ESCAPE_RADIUS: f32 = 1000.0;
+const HALF_PI: f32 = 1.5707963;
+
+struct Trace {
+    // direction the ray escapes in
+    direction: vec3<f32>,
+    // light picked up on the way
+    color: vec3<f32>,
+    // how much of the sky shows through, 0 if the ray fell in or hit the disk
+    transmittance: f32,
+};
+
+// The black hole sits at the origin with its spin axis along y in its own frame, which is tilted
+// around x by the inclination.
+fn to_hole_frame(v: vec3<f32>) -> vec3<f32> {
+    let c = cos(r_data.black_hole.inclination);
+    let s = sin(r_data.black_hole.inclination);
+    return vec3<f32>(v.x, c * v.y + s * v.z, c * v.z - s * v.y);
 }
 
-@fragment
-fn fs_entity(vertex: EntityOutput) -> @location(0) vec4<f32> {
-    let world_pos = vertex.world_position.xyz;
-    let view_dir = normalize(-vertex.view);
-    let normal = normalize(vertex.normal);
-
-    // Define black hole parameters
-    let black_hole_radius = 5.0;
-    let schwarzschild_radius = 2.0 * black_hole_radius;
-    let disk_inner_radius = 6.0;
-    let disk_outer_radius = 12.0;
-    let disk_thickness = 0.5;
-
-    // Define disk orientation (assuming it's aligned with the xz plane)
-    let disk_normal = vec3<f32>(1.0, 0.0, 0.0);
-
-    // Calculate distance from black hole center
-    let distance_from_center = length(world_pos);
-
-    // Gravitational lensing
-    let lensing_strength = 15.0;
-    let deflection = lensing_strength * schwarzschild_radius / distance_from_center;
-    let lensed_dir = normalize(view_dir + deflection * normalize(world_pos - view_dir * dot(world_pos, view_dir)));
-
-    // Check if we're rendering the black hole
-    if distance_from_center < black_hole_radius {
-        return vec4<f32>(0.0, 0.0, 0.0, 1.0); // Black hole is completely black
+fn from_hole_frame(v: vec3<f32>) -> vec3<f32> {
+    let c = cos(r_data.black_hole.inclination);
+    let s = sin(r_data.black_hole.inclination);
+    return vec3<f32>(v.x, c * v.y - s * v.z, c * v.z + s * v.y);
+}
+
+fn on_disk(radius: f32) -> bool {
+    return radius >= r_data.black_hole.disk_inner_radius && radius <= r_data.black_hole.disk_outer_radius;
+}
+
+// Temperature scale at accretion rate 1, puts the hottest ring around a non-rotating hole at 5000 K.
+const DISK_TEMPERATURE: f32 = 39290.0;
+// Blackbodies this hot come out at 63% brightness, hotter ones saturate towards white.
+const EXPOSURE_TEMPERATURE: f32 = 5000.0;
+
+// Shakura-Sunyaev thin disk: σT⁴ ∝ Ṁ M / r³ (1 - sqrt(r_in / r)), with no torque at the inner edge.
+fn disk_temperature(radius: f32) -> f32 {
+    let black_hole = r_data.black_hole;
+    let x = black_hole.mass / radius;
+    let t4 = black_hole.accretion_rate * x * x * x * (1.0 - sqrt(black_hole.disk_inner_radius / radius));
+    return DISK_TEMPERATURE * sqrt(sqrt(max(t4, 0.0)));
+}
+
+// Range of the blackbody lookup texture, see blackbody.rs.
+const BLACKBODY_MIN_TEMPERATURE: f32 = 1000.0;
+const BLACKBODY_MAX_TEMPERATURE: f32 = 40000.0;
+
+// Linear sRGB color of a blackbody with unit luminance.
+fn blackbody_color(kelvin: f32) -> vec3<f32> {
+    // Texels are spaced logarithmically in temperature
+    let t = log(kelvin / BLACKBODY_MIN_TEMPERATURE) / log(BLACKBODY_MAX_TEMPERATURE / BLACKBODY_MIN_TEMPERATURE);
+    let texel_count = f32(textureDimensions(r_blackbody).x);
+    let u = (clamp(t, 0.0, 1.0) * (texel_count - 1.0) + 0.5) / texel_count;
+    let rgb = textureSampleLevel(r_blackbody, r_sampler, vec2<f32>(u, 0.5), 0.0).rgb;
+    return rgb / dot(rgb, vec3<f32>(0.2126, 0.7152, 0.0722));
+}
+
+// Light from the disk at `radius`, where `redshift` is the observed over the emitted frequency.
+// A shifted blackbody stays a blackbody at the shifted temperature, and I_ν / ν³ is invariant,
+// so the total intensity grows with the fourth power of the observed temperature.
+fn disk_emission(radius: f32, redshift: f32) -> vec3<f32> {
+    let temperature = redshift * disk_temperature(radius);
+    let brightness = pow(temperature / EXPOSURE_TEMPERATURE, 4.0);
+    return vec3<f32>(1.0) - exp(-blackbody_color(temperature) * brightness);
+}
+
+// Step size controller for the embedded Bogacki-Shampine 3(2) pairs below.
+fn next_step(step: f32, error: f32, r: f32) -> f32 {
+    let scale = clamp(0.9 * pow(r_data.black_hole.tolerance / max(error, 1e-30), 1.0 / 3.0), 0.2, 5.0);
+    // Straight rays have no error to go by, don't let them jump across the horizon or the disk
+    return min(step * scale, 0.5 * r);
+}
+
+// Null geodesics around a Schwarzschild mass, written in Cartesian coordinates:
+// x'' = -3 M h² x / r⁵, where h = |x × x'| is conserved along the ray.
+fn schwarzschild_acceleration(pos: vec3<f32>, h2: f32) -> vec3<f32> {
+    let r2 = max(dot(pos, pos), 1e-12);
+    return -3.0 * r_data.black_hole.mass * h2 * pos / (r2 * r2 * sqrt(r2));
+}
+
+// Follow a light ray backwards from the camera, in the hole's frame.
+// The ray starts along the coordinate direction, which is close to what a static observer sees
+// as long as the camera stays well outside the photon sphere.
+fn trace_schwarzschild(origin: vec3<f32>, direction: vec3<f32>) -> Trace {
+    let black_hole = r_data.black_hole;
+    let horizon = 2.0 * black_hole.mass;
+    let escape_radius = max(ESCAPE_RADIUS * black_hole.mass, length(origin));
+
+    var result: Trace;
+    result.direction = direction;
+    result.color = vec3<f32>(0.0);
+    result.transmittance = 0.0;
+
+    var pos = origin;
+    var vel = direction;
+    let h2 = dot(cross(pos, vel), cross(pos, vel));
+    // Angular momentum of the arriving light around the spin axis, over its energy
+    let angular_momentum = -cross(pos, vel).y;
+    var acc = schwarzschild_acceleration(pos, h2);
+    var step = 0.1 * length(pos);
+
+    for (var i = 0; i < i32(black_hole.max_steps); i++) {
+        let r = length(pos);
+        if r < horizon {
+            return result;
+        }
+        if r >= escape_radius && dot(pos, vel) > 0.0 {
+            result.direction = normalize(vel);
+            result.transmittance = 1.0;
+            return result;
+        }
+
+        let vel2 = vel + 0.5 * step * acc;
+        let acc2 = schwarzschild_acceleration(pos + 0.5 * step * vel, h2);
+        let vel3 = vel + 0.75 * step * acc2;
+        let acc3 = schwarzschild_acceleration(pos + 0.75 * step * vel2, h2);
+        let next_pos = pos + step * (2.0 / 9.0 * vel + 1.0 / 3.0 * vel2 + 4.0 / 9.0 * vel3);
+        let next_vel = vel + step * (2.0 / 9.0 * acc + 1.0 / 3.0 * acc2 + 4.0 / 9.0 * acc3);
+        let next_acc = schwarzschild_acceleration(next_pos, h2);
+
+        // Difference to the embedded second order solution
+        let pos_error = step * (-5.0 / 72.0 * vel + 1.0 / 12.0 * vel2 + 1.0 / 9.0 * vel3 - 1.0 / 8.0 * next_vel);
+        let vel_error = step * (-5.0 / 72.0 * acc + 1.0 / 12.0 * acc2 + 1.0 / 9.0 * acc3 - 1.0 / 8.0 * next_acc);
+        let error = max(length(pos_error) / r, length(vel_error) / length(vel));
+
+        if error <= black_hole.tolerance {
+            // Crossing the equatorial plane
+            if pos.y * next_pos.y <= 0.0 && pos.y != next_pos.y {
+                let hit = mix(pos, next_pos, pos.y / (pos.y - next_pos.y));
+                let disk_radius = length(hit.xz);
+                if on_disk(disk_radius) {
+                    // Keplerian orbit around the y axis
+                    let m = black_hole.mass;
+                    let omega = sqrt(m / (disk_radius * disk_radius * disk_radius));
+                    let ut = 1.0 / sqrt(1.0 - 3.0 * m / disk_radius);
+                    let redshift = 1.0 / (ut * (1.0 - omega * angular_momentum));
+                    result.color = disk_emission(disk_radius, redshift);
+                    return result;
+                }
+            }
+
+            pos = next_pos;
+            vel = next_vel;
+            acc = next_acc;
+        }
+        step = next_step(step, error, r);
+    }
+
+    // Still circling the photon sphere, hardly any light makes it out of there
+    return result;
+}
+
+// Boyer-Lindquist coordinates (r, θ, φ) and the momenta (p_r, p_θ) of a photon with unit energy,
+// the angular momentum p_φ is conserved.
+struct KerrState {
+    q: vec3<f32>,
+    p: vec2<f32>,
+};
+
+// Hamilton's equations for H = (Δ p_r² + p_θ² - P²/Δ + (L - a sin²θ)² / sin²θ) / 2Σ = 0,
+// with P = r² + a² - a L. They run backwards in time, since rays are followed from the camera
+// to where the light came from, and with a spinning hole that isn't the same path in reverse.
+fn kerr_derivative(state: KerrState, angular_momentum: f32) -> KerrState {
+    let m = r_data.black_hole.mass;
+    let a = r_data.black_hole.spin * m;
+    let l = angular_momentum;
+    let r = state.q.x;
+    // The poles are a coordinate singularity, rays passing right through them get a little kink
+    let sin_theta = max(sin(state.q.y), 1e-4);
+    let cos_theta = cos(state.q.y);
+
+    let sigma = r * r + a * a * cos_theta * cos_theta;
+    let delta = r * r - 2.0 * m * r + a * a;
+    let p = r * r + a * a - a * l;
+
+    var d: KerrState;
+    d.q = -vec3<f32>(
+        delta * state.p.x,
+        state.p.y,
+        a * p / delta + l / (sin_theta * sin_theta) - a,
+    ) / sigma;
+
+    let d_delta = 2.0 * r - 2.0 * m;
+    let dh_dr = d_delta * state.p.x * state.p.x - 4.0 * r * p / delta + p * p * d_delta / (delta * delta);
+    let dh_dtheta = -2.0 * l * l * cos_theta / (sin_theta * sin_theta * sin_theta) + 2.0 * a * a * sin_theta * cos_theta;
+    d.p = vec2<f32>(dh_dr, dh_dtheta) / (2.0 * sigma);
+    return d;
+}
+
+fn kerr_add(state: KerrState, d: KerrState, step: f32) -> KerrState {
+    return KerrState(state.q + step * d.q, state.p + step * d.p);
+}
+
+// Unit vectors along r, θ and φ, close to the Boyer-Lindquist ones far from the hole.
+fn spherical_basis(theta: f32, phi: f32) -> mat3x3<f32> {
+    let sin_theta = sin(theta);
+    let cos_theta = cos(theta);
+    let sin_phi = sin(phi);
+    let cos_phi = cos(phi);
+    return mat3x3<f32>(
+        vec3<f32>(sin_theta * sin_phi, cos_theta, sin_theta * cos_phi),
+        vec3<f32>(cos_theta * sin_phi, -sin_theta, cos_theta * cos_phi),
+        vec3<f32>(cos_phi, 0.0, -sin_phi),
+    );
+}
+
+// Like `trace_schwarzschild`, but the light arrives in the frame of an observer that is dragged
+// along with the hole's rotation.
+fn trace_kerr(origin: vec3<f32>, direction: vec3<f32>) -> Trace {
+    let black_hole = r_data.black_hole;
+    let m = black_hole.mass;
+    let a = black_hole.spin * m;
+    let horizon = m + sqrt(max(m * m - a * a, 0.0));
+
+    var result: Trace;
+    result.direction = direction;
+    result.color = vec3<f32>(0.0);
+    result.transmittance = 0.0;
+
+    // Boyer-Lindquist coordinates of the camera, x and z are spread over sqrt(r² + a²)
+    let w = dot(origin, origin) - a * a;
+    let r = sqrt(0.5 * (w + sqrt(w * w + 4.0 * a * a * origin.y * origin.y)));
+    if r <= horizon * 1.01 {
+        return result;
     }
+    let theta = acos(clamp(origin.y / r, -1.0, 1.0));
+    let phi = atan2(origin.x, origin.z);
+    let escape_radius = max(ESCAPE_RADIUS * m, r);
+
+    // Momentum of the light arriving at the camera from `direction`, as seen by the zero angular
+    // momentum observer there
+    let n = -direction * spherical_basis(theta, phi);
+    let sin_theta = max(sin(theta), 1e-4);
+    let sigma = r * r + a * a * cos(theta) * cos(theta);
+    let delta = r * r - 2.0 * m * r + a * a;
+    let big_a = (r * r + a * a) * (r * r + a * a) - a * a * delta * sin_theta * sin_theta;
+    let omega = 2.0 * m * a * r / big_a;
+    let lapse = sqrt(sigma * delta / big_a);
+    let l_local = sqrt(big_a / sigma) * sin_theta * n.z;
+    let energy = lapse + omega * l_local;
+    let angular_momentum = l_local / energy;
+
+    var state = KerrState(
+        vec3<f32>(r, theta, phi),
+        vec2<f32>(sqrt(sigma / delta) * n.x, sqrt(sigma) * n.y) / energy,
+    );
+    var d = kerr_derivative(state, angular_momentum);
+    var step = 0.1 * r;
+
+    for (var i = 0; i < i32(black_hole.max_steps); i++) {
+        let r = state.q.x;
+        if r < horizon * 1.01 {
+            return result;
+        }
+        if r >= escape_radius && d.q.x > 0.0 {
+            let basis = spherical_basis(state.q.y, state.q.z);
+            result.direction = normalize(basis * (d.q * vec3<f32>(1.0, r, r * sin(state.q.y))));
+            result.transmittance = 1.0;
+            return result;
+        }
+
+        let d2 = kerr_derivative(kerr_add(state, d, 0.5 * step), angular_momentum);
+        let d3 = kerr_derivative(kerr_add(state, d2, 0.75 * step), angular_momentum);
+        let next = KerrState(
+            state.q + step * (2.0 / 9.0 * d.q + 1.0 / 3.0 * d2.q + 4.0 / 9.0 * d3.q),
+            state.p + step * (2.0 / 9.0 * d.p + 1.0 / 3.0 * d2.p + 4.0 / 9.0 * d3.p),
+        );
+        let next_d = kerr_derivative(next, angular_momentum);
+
+        // Difference to the embedded second order solution, as distances relative to r
+        let q_error = step * (-5.0 / 72.0 * d.q + 1.0 / 12.0 * d2.q + 1.0 / 9.0 * d3.q - 1.0 / 8.0 * next_d.q);
+        let p_error = step * (-5.0 / 72.0 * d.p + 1.0 / 12.0 * d2.p + 1.0 / 9.0 * d3.p - 1.0 / 8.0 * next_d.p);
+        let error = max(
+            max(abs(q_error.x) / r, max(abs(q_error.y), abs(q_error.z) * sin(state.q.y))),
+            max(abs(p_error.x), abs(p_error.y) / r),
+        );
 
-    // Calculate disk intersection
-    let disk_t = dot(world_pos, disk_normal) / dot(lensed_dir, disk_normal);
-    let disk_intersection = world_pos - lensed_dir * disk_t;
-    let disk_distance = length(disk_intersection);
-
-    // Check if we're rendering the accretion disk
-    if abs(dot(normalize(disk_intersection), disk_normal)) < disk_thickness &&
-       disk_distance > disk_inner_radius && disk_distance < disk_outer_radius {
-        // Calculate disk color with Doppler shift
-        let orbital_velocity = sqrt(1.0 / disk_distance) * 0.5; // Simplified orbital velocity
-        let doppler_factor = 1.0 / (1.0 - orbital_velocity * dot(normalize(disk_intersection), lensed_dir));
-        let base_color = mix(vec3<f32>(1.0, 0.0, 0.0), vec3<f32>(1.0, 1.0, 0.0), (disk_distance - disk_inner_radius) / (disk_outer_radius - disk_inner_radius));
-        let shifted_color = base_color * doppler_factor;
-        return vec4<f32>(shifted_color, 1.0);
+        if error <= black_hole.tolerance {
+            let below = state.q.y - HALF_PI;
+            let next_below = next.q.y - HALF_PI;
+            if below * next_below <= 0.0 && below != next_below {
+                let disk_radius = mix(state.q.x, next.q.x, below / (below - next_below));
+                if on_disk(disk_radius) {
+                    // Circular equatorial orbit in the direction of positive spin
+                    let sqrt_m = sqrt(m);
+                    let r32 = disk_radius * sqrt(disk_radius);
+                    let omega = sqrt_m / (r32 + a * sqrt_m);
+                    let ut = (r32 + a * sqrt_m) / (sqrt(r32) * sqrt(r32 - 3.0 * m * sqrt(disk_radius) + 2.0 * a * sqrt_m));
+                    let redshift = 1.0 / (ut * (1.0 - omega * angular_momentum));
+                    result.color = disk_emission(disk_radius, redshift);
+                    return result;
+                }
+            }
+
+            state = next;
+            d = next_d;
+        }
+        step = next_step(step, error, r);
     }
 
-    // Ambient lighting
-    let ambient_color = vec3<f32>(0.1, 0.1, 0.15);
-    let ambient_strength = 0.2;
-    let ambient = ambient_strength * ambient_color;
+    return result;
+}
+
+@fragment
+fn fs_universe(vertex: UniverseOutput) -> @location(0) vec4<f32> {
+    let origin = to_hole_frame(r_data.camera.position.xyz);
+    let direction = to_hole_frame(normalize(vertex.ray_dir));
 
-    // Diffuse shading
-    let light_dir = normalize(vec3<f32>(1.0, 1.0, 1.0)); // Directional light
-    let light_color = vec3<f32>(1.0, 1.0, 0.9);
-    let diff = max(dot(normal, light_dir), 0.0);
-    let diffuse = diff * light_color;
+    var trace: Trace;
+    if r_data.black_hole.metric > 0.5 {
+        trace = trace_kerr(origin, direction);
+    } else {
+        trace = trace_schwarzschild(origin, direction);
+    }
+
+    // The escaped direction jumps around near the photon ring, so derivatives can't pick a mip
+    let sky = textureSampleLevel(r_texture, r_sampler, from_hole_frame(trace.direction), 0.0).rgb;
+    return vec4<f32>(trace.color + trace.transmittance * sky, 1.0);
+}
+
+// Lit color of an entity surface with this world space normal
+fn shade_entity(normal: vec3<f32>, view: vec3<f32>, albedo: vec3<f32>) -> vec3<f32> {
+    let lighting = r_data.lighting;
+    let view_dir = normalize(-view);
+
+    // Diffuse shading from a directional light
+    let diff = max(dot(normal, lighting.direction), 0.0);
+    let diffuse = diff * lighting.color;
 
     // Specular reflection
-    let specular_strength = 0.8;
-    let shininess = 64.0;
-    let reflect_dir = reflect(-light_dir, normal);
-    let spec = pow(max(dot(view_dir, reflect_dir), 0.0), shininess);
-    let specular = specular_strength * spec * light_color;
+    let reflect_dir = reflect(-lighting.direction, normal);
+    let spec = pow(max(dot(view_dir, reflect_dir), 0.0), lighting.shininess);
+    let specular = lighting.specular_strength * spec * lighting.color;
 
     // Reflection
-    let fresnel_factor = pow(1.0 - max(dot(view_dir, normal), 0.0), 3.0);
-    let fresnel_strength = 0.5;
-    let reflected = reflect(normalize(vertex.view), normal);
-    let reflected_color = textureSample(r_texture, r_sampler, reflected).rgb;
-    let lensed_reflection = textureSample(r_texture, r_sampler, lensed_dir).rgb;
-    let reflection = mix(reflected_color, lensed_reflection, fresnel_factor * fresnel_strength);
+    let reflected = reflect(normalize(view), normal);
+    let reflection = textureSample(r_texture, r_sampler, reflected).rgb;
 
-    let lighting_color = ambient + diffuse + specular;
-    let surface_color = lighting_color * 0.3 + reflection * 0.7;
+    let lighting_color = (lighting.ambient_color + diffuse) * albedo + specular;
+    return mix(lighting_color, reflection, lighting.reflectivity);
+}
 
+@fragment
+fn fs_entity(vertex: EntityOutput) -> @location(0) vec4<f32> {
+    let surface_color = shade_entity(normalize(vertex.normal), vertex.view, r_entity.color.rgb);
     return vec4<f32>(surface_color, 1.0);
 }
+
+@group(2)
+@binding(0)
+var r_albedo: texture_2d<f32>;
+
+@group(2)
+@binding(1)
+var r_normal_map: texture_2d<f32>;
+
+@group(2)
+@binding(2)
+var r_material_sampler: sampler;
+
+struct TexturedEntityOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) world_position: vec4<f32>,
+    @location(1) normal: vec3<f32>,
+    @location(2) view: vec3<f32>,
+    @location(3) uv: vec2<f32>,
+    @location(4) tangent: vec4<f32>,
+};
+
+@vertex
+fn vs_textured_entity(
+    @location(0) pos: vec3<f32>,
+    @location(1) normal: vec3<f32>,
+    @location(2) uv: vec2<f32>,
+    @location(3) tangent: vec4<f32>,
+) -> TexturedEntityOutput {
+    var result: TexturedEntityOutput;
+
+    let world_pos = r_entity.model * vec4<f32>(pos, 1.0);
+    result.world_position = world_pos;
+    result.position = r_data.camera.proj * r_data.camera.view * world_pos;
+    result.normal = normalize(r_entity.normal * normal);
+    result.view = (r_data.camera.view * world_pos).xyz;
+    result.uv = uv;
+    // Tangents lie in the surface, so they follow the model matrix, but then need to be made
+    // perpendicular to the normal again
+    let world_tangent = (r_entity.model * vec4<f32>(tangent.xyz, 0.0)).xyz;
+    let orthogonal = world_tangent - result.normal * dot(result.normal, world_tangent);
+    result.tangent = vec4<f32>(normalize(orthogonal), tangent.w);
+    return result;
+}
+
+@fragment
+fn fs_textured_entity(vertex: TexturedEntityOutput) -> @location(0) vec4<f32> {
+    // Interpolation leaves the frame slightly skewed, straighten it out again
+    let n = normalize(vertex.normal);
+    let t = normalize(vertex.tangent.xyz - n * dot(n, vertex.tangent.xyz));
+    let b = cross(n, t) * vertex.tangent.w;
+
+    let texel = textureSample(r_normal_map, r_material_sampler, vertex.uv).xyz * 2.0 - 1.0;
+    let normal = normalize(mat3x3<f32>(t, b, n) * texel);
+
+    let albedo = textureSample(r_albedo, r_material_sampler, vertex.uv).rgb * r_entity.color.rgb;
+    return vec4<f32>(shade_entity(normal, vertex.view, albedo), 1.0);
+}
+
+struct LineOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) color: vec4<f32>,
+};
+
+// Each segment is a quad, widened here in screen space so lines keep their width in pixels.
+@vertex
+fn vs_line(
+    @location(0) start: vec3<f32>,
+    @location(1) end: vec3<f32>,
+    @location(2) color: vec4<f32>,
+    @location(3) width: f32,
+    @location(4) along: f32,
+    @location(5) side: f32,
+) -> LineOutput {
+    var result: LineOutput;
+    result.color = color;
+
+    let view_proj = r_data.camera.proj * r_data.camera.view;
+    let clip_start = view_proj * vec4<f32>(start, 1.0);
+    let clip_end = view_proj * vec4<f32>(end, 1.0);
+    // Segments reaching behind the camera have no screen direction, they are dropped behind the far plane
+    if (min(clip_start.w, clip_end.w) <= 0.0) {
+        result.position = vec4<f32>(0.0, 0.0, 2.0, 1.0);
+        return result;
+    }
+
+    let screen_size = r_data.camera.screen_size;
+    let screen_dir = (clip_end.xy / clip_end.w - clip_start.xy / clip_start.w) * screen_size;
+    var dir = vec2<f32>(1.0, 0.0);
+    if (dot(screen_dir, screen_dir) > 0.0) {
+        dir = normalize(screen_dir);
+    }
+    // Half the width in NDC, which spans 2 across the screen
+    let offset = vec2<f32>(-dir.y, dir.x) * side * width / screen_size;
+
+    let clip = mix(clip_start, clip_end, along);
+    result.position = vec4<f32>(clip.xy + offset * clip.w, clip.zw);
+    return result;
+}
+
+@fragment
+fn fs_line(vertex: LineOutput) -> @location(0) vec4<f32> {
+    return vertex.color;
+}
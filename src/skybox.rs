@@ -0,0 +1,482 @@
+use std::path::{Path, PathBuf};
+
+use wgpu::{util::DeviceExt, AstcBlock, AstcChannel};
+
+use crate::texture;
+
+/// Face file names in cubemap layer order (+X, -X, +Y, -Y, +Z, -Z).
+pub const FACE_NAMES: [&str; 6] = ["right", "left", "top", "bottom", "front", "back"];
+
+/// Directory with the face images shipped in the repository, relative to its root.
+pub const DEFAULT_FACES_DIR: &str = "src/assets/images/skybox_src_images";
+
+const FACE_EXTENSIONS: [&str; 3] = ["jpg", "jpeg", "png"];
+
+/// Skybox formats in order of preference, each with the name of the KTX2
+/// variant `assets/images/generation.bash` writes for it.
+const KTX2_VARIANTS: [(wgpu::TextureFormat, &str); 4] = [
+    (
+        wgpu::TextureFormat::Astc {
+            block: AstcBlock::B4x4,
+            channel: AstcChannel::UnormSrgb,
+        },
+        "astc.ktx2",
+    ),
+    (wgpu::TextureFormat::Bc7RgbaUnormSrgb, "bc7.ktx2"),
+    (wgpu::TextureFormat::Etc2Rgb8A1UnormSrgb, "etc2.ktx2"),
+    (wgpu::TextureFormat::Rgba8UnormSrgb, "rgba8.ktx2"),
+];
+
+/// Where the skybox cubemap is loaded from.
+#[derive(Debug, Clone)]
+pub enum SkyboxSource {
+    /// A single KTX2 cubemap file. If the adapter can't sample its format, the directory it is
+    /// in is searched like [`SkyboxSource::Directory`] instead.
+    Ktx2(PathBuf),
+    /// A directory holding KTX2 variants named after their format (`astc.ktx2`,
+    /// `bc7.ktx2`, `etc2.ktx2`, `rgba8.ktx2`), or one image per face named after [`FACE_NAMES`].
+    Directory(PathBuf),
+    /// The KTX2 file baked into the binary.
+    #[cfg(feature = "embedded-skybox")]
+    Embedded,
+}
+
+impl SkyboxSource {
+    /// Directories are searched for variants or face images, anything else is read as a KTX2 file.
+    pub fn from_path(path: &Path) -> Self {
+        if path.is_dir() {
+            Self::Directory(path.to_path_buf())
+        } else {
+            Self::Ktx2(path.to_path_buf())
+        }
+    }
+
+    /// The skybox used without `--skybox` or one in the scene file.
+    pub fn find_default() -> Result<Self, anyhow::Error> {
+        cfg_if::cfg_if! {
+            if #[cfg(feature = "embedded-skybox")] {
+                Ok(Self::Embedded)
+            } else {
+                find_default_faces_dir().map(Self::Directory).ok_or_else(|| {
+                    anyhow::anyhow!(
+                        "No skybox found at {} in the current directory or next to the \
+                         executable, pass one with --skybox",
+                        DEFAULT_FACES_DIR
+                    )
+                })
+            }
+        }
+    }
+}
+
+/// [`DEFAULT_FACES_DIR`] below the current directory or the executable's, whichever exists.
+/// Debug builds also look in the source tree they were built from.
+fn find_default_faces_dir() -> Option<PathBuf> {
+    let exe_dir = std::env::current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(Path::to_path_buf));
+    #[cfg(debug_assertions)]
+    let source_dir = Some(PathBuf::from(env!("CARGO_MANIFEST_DIR")));
+    #[cfg(not(debug_assertions))]
+    let source_dir = None;
+
+    [Some(PathBuf::new()), exe_dir, source_dir]
+        .into_iter()
+        .flatten()
+        .map(|base| base.join(DEFAULT_FACES_DIR))
+        .find(|dir| dir.is_dir())
+}
+
+/// Load the skybox from `source` and upload it as a 6 layer cube-compatible texture.
+pub fn create_texture(
+    source: &SkyboxSource,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+) -> Result<wgpu::Texture, anyhow::Error> {
+    match source {
+        SkyboxSource::Ktx2(path) => {
+            let bytes = std::fs::read(path)
+                .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", path.display(), e))?;
+            let dir = path.parent().unwrap_or(Path::new(""));
+            create_ktx2_or_fallback(&bytes, &path.display().to_string(), dir, device, queue)
+        }
+        SkyboxSource::Directory(dir) => create_directory_texture(dir, device, queue),
+        #[cfg(feature = "embedded-skybox")]
+        SkyboxSource::Embedded => create_ktx2_or_fallback(
+            include_bytes!("assets/images/skybox.ktx2"),
+            "The embedded skybox",
+            &find_default_faces_dir().unwrap_or_else(|| PathBuf::from(DEFAULT_FACES_DIR)),
+            device,
+            queue,
+        ),
+    }
+}
+
+/// Use the best variant in `dir` the adapter can sample, and only decode the face images on
+/// the CPU if there is none.
+fn create_directory_texture(
+    dir: &Path,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+) -> Result<wgpu::Texture, anyhow::Error> {
+    let device_features = device.features();
+    let variant = KTX2_VARIANTS
+        .iter()
+        .filter(|(format, _)| device_features.contains(format.required_features()))
+        .map(|(format, file_name)| (*format, dir.join(file_name)))
+        .find(|(_, path)| path.is_file());
+
+    match variant {
+        Some((format, path)) => {
+            log::info!("Using skybox variant {}", path.display());
+            let bytes = std::fs::read(&path)
+                .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", path.display(), e))?;
+            create_ktx2_texture(&bytes, Some(format), device, queue)
+                .map_err(|e| e.context(format!("Invalid skybox {}", path.display())))
+        }
+        None => create_faces_texture(dir, device, queue),
+    }
+}
+
+/// Upload a KTX2 cubemap, or if the adapter can't sample its compressed format, whatever
+/// [`create_directory_texture`] finds in `fallback_dir` instead, e.g. the `rgba8.ktx2` variant.
+fn create_ktx2_or_fallback(
+    bytes: &[u8],
+    name: &str,
+    fallback_dir: &Path,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+) -> Result<wgpu::Texture, anyhow::Error> {
+    let format = ktx2::Reader::new(bytes)
+        .ok()
+        .and_then(|reader| reader.header().format)
+        .and_then(texture_format);
+    match format {
+        Some(format) if !device.features().contains(format.required_features()) => {
+            log::warn!(
+                "{} is stored as {:?}, which the adapter can't sample, looking for another \
+                 skybox in {}",
+                name,
+                format,
+                fallback_dir.display()
+            );
+            create_directory_texture(fallback_dir, device, queue).map_err(|e| {
+                e.context(format!(
+                    "{} is stored as {:?}, which the adapter can't sample, and there is no \
+                     fallback",
+                    name, format
+                ))
+            })
+        }
+        _ => create_ktx2_texture(bytes, None, device, queue),
+    }
+}
+
+/// Map the KTX2 (Vulkan) format to the matching wgpu texture format.
+fn texture_format(format: ktx2::Format) -> Option<wgpu::TextureFormat> {
+    use wgpu::TextureFormat as Tf;
+
+    let astc = |block| Tf::Astc {
+        block,
+        channel: AstcChannel::UnormSrgb,
+    };
+
+    Some(match format {
+        ktx2::Format::R8G8B8A8_SRGB => Tf::Rgba8UnormSrgb,
+        ktx2::Format::BC7_SRGB_BLOCK => Tf::Bc7RgbaUnormSrgb,
+        ktx2::Format::ETC2_R8G8B8_SRGB_BLOCK => Tf::Etc2Rgb8UnormSrgb,
+        ktx2::Format::ETC2_R8G8B8A1_SRGB_BLOCK => Tf::Etc2Rgb8A1UnormSrgb,
+        ktx2::Format::ETC2_R8G8B8A8_SRGB_BLOCK => Tf::Etc2Rgba8UnormSrgb,
+        ktx2::Format::ASTC_4x4_SRGB_BLOCK => astc(AstcBlock::B4x4),
+        ktx2::Format::ASTC_5x5_SRGB_BLOCK => astc(AstcBlock::B5x5),
+        ktx2::Format::ASTC_6x6_SRGB_BLOCK => astc(AstcBlock::B6x6),
+        ktx2::Format::ASTC_8x8_SRGB_BLOCK => astc(AstcBlock::B8x8),
+        ktx2::Format::ASTC_10x10_SRGB_BLOCK => astc(AstcBlock::B10x10),
+        ktx2::Format::ASTC_12x12_SRGB_BLOCK => astc(AstcBlock::B12x12),
+        _ => return None,
+    })
+}
+
+/// Upload a KTX2 cubemap, taking its size, mip count and format from the header.
+///
+/// If `expected_format` is given, the file has to be stored in exactly that format.
+fn create_ktx2_texture(
+    bytes: &[u8],
+    expected_format: Option<wgpu::TextureFormat>,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+) -> Result<wgpu::Texture, anyhow::Error> {
+    let reader = ktx2::Reader::new(bytes)
+        .map_err(|e| anyhow::anyhow!("Failed to create KTX2 reader: {}", e))?;
+    let header = reader.header();
+
+    let skybox_format = header
+        .format
+        .and_then(texture_format)
+        .ok_or_else(|| anyhow::anyhow!("Unsupported KTX2 texture format {:?}", header.format))?;
+
+    if let Some(expected_format) = expected_format {
+        if skybox_format != expected_format {
+            return Err(anyhow::anyhow!(
+                "KTX2 file is stored as {:?}, expected {:?}",
+                skybox_format,
+                expected_format
+            ));
+        }
+    }
+
+    let missing_features = skybox_format.required_features() - device.features();
+    if !missing_features.is_empty() {
+        return Err(anyhow::anyhow!(
+            "Skybox format {:?} needs {:?}, which the adapter does not support",
+            skybox_format,
+            missing_features
+        ));
+    }
+
+    if let Some(scheme) = header.supercompression_scheme {
+        return Err(anyhow::anyhow!(
+            "Supercompressed KTX2 files ({:?}) are not supported",
+            scheme
+        ));
+    }
+
+    if header.face_count != 6 {
+        return Err(anyhow::anyhow!(
+            "KTX2 file has {} faces, a cubemap needs 6",
+            header.face_count
+        ));
+    }
+
+    if header.pixel_depth != 0 || header.layer_count > 1 {
+        return Err(anyhow::anyhow!(
+            "KTX2 file is a {}x{}x{} texture with {} layers, expected a single 2D cubemap",
+            header.pixel_width,
+            header.pixel_height,
+            header.pixel_depth,
+            header.layer_count
+        ));
+    }
+
+    let image_size = header.pixel_width;
+    if header.pixel_height != image_size {
+        return Err(anyhow::anyhow!(
+            "KTX2 faces are {}x{}, cubemap faces must be square",
+            header.pixel_width,
+            header.pixel_height
+        ));
+    }
+
+    let max_dimension = device.limits().max_texture_dimension_2d;
+    if image_size > max_dimension {
+        return Err(anyhow::anyhow!(
+            "KTX2 faces are {}x{}, but the device only supports textures up to {}x{}",
+            image_size,
+            image_size,
+            max_dimension,
+            max_dimension,
+        ));
+    }
+
+    // Compressed formats can only hold whole blocks.
+    let (block_width, block_height) = skybox_format.block_dimensions();
+    if !image_size.is_multiple_of(block_width) || !image_size.is_multiple_of(block_height) {
+        return Err(anyhow::anyhow!(
+            "Skybox size {} is not a multiple of the {}x{} blocks of {:?}",
+            image_size,
+            block_width,
+            block_height,
+            skybox_format
+        ));
+    }
+
+    let size = wgpu::Extent3d {
+        width: image_size,
+        height: image_size,
+        depth_or_array_layers: 6,
+    };
+
+    let layer_size = wgpu::Extent3d {
+        depth_or_array_layers: 1,
+        ..size
+    };
+    let max_mips = layer_size.max_mips(wgpu::TextureDimension::D2);
+
+    // A level count of 0 asks the loader to generate mips, we just use the base level.
+    let mip_level_count = header.level_count.max(1);
+    if mip_level_count > max_mips {
+        return Err(anyhow::anyhow!(
+            "KTX2 file has {} mip levels, a {}x{} texture can have at most {}",
+            mip_level_count,
+            image_size,
+            image_size,
+            max_mips
+        ));
+    }
+
+    log::info!(
+        "Copying {:?} skybox images of size {}, {}, 6 with {} mips to gpu",
+        skybox_format,
+        image_size,
+        image_size,
+        mip_level_count,
+    );
+
+    // Every level must hold six faces worth of blocks, smaller levels are rounded up to whole blocks.
+    let block_size = skybox_format
+        .block_copy_size(None)
+        .expect("skybox formats have a single aspect");
+    for (level, data) in reader.levels().enumerate() {
+        let level_size = layer_size.mip_level_size(level as u32, wgpu::TextureDimension::D2);
+        let expected = level_size.width.div_ceil(block_width)
+            * level_size.height.div_ceil(block_height)
+            * block_size
+            * 6;
+        if data.len() != expected as usize {
+            return Err(anyhow::anyhow!(
+                "Skybox mip level {} holds {} bytes, expected {} for {:?}",
+                level,
+                data.len(),
+                expected,
+                skybox_format
+            ));
+        }
+    }
+
+    let mut image = Vec::with_capacity(reader.data().len());
+    for level in reader.levels() {
+        image.extend_from_slice(level);
+    }
+
+    Ok(device.create_texture_with_data(
+        queue,
+        &wgpu::TextureDescriptor {
+            size,
+            mip_level_count,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: skybox_format,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            label: None,
+            view_formats: &[],
+        },
+        // KTX2 stores mip levels in mip major order.
+        wgpu::util::TextureDataOrder::MipMajor,
+        &image,
+    ))
+}
+
+/// Decode the face images in `dir`, in [`FACE_NAMES`] order. They have to be square and equally sized.
+pub fn load_faces(dir: &Path) -> Result<Vec<image::RgbaImage>, anyhow::Error> {
+    let mut faces = Vec::with_capacity(FACE_NAMES.len());
+    for name in FACE_NAMES {
+        let path = find_face(dir, name)?;
+        let face = image::open(&path)
+            .map_err(|e| anyhow::anyhow!("Failed to decode {}: {}", path.display(), e))?
+            .to_rgba8();
+        faces.push(face);
+    }
+
+    let image_size = faces[0].width();
+    for (name, face) in FACE_NAMES.iter().zip(faces.iter()) {
+        if face.width() != face.height() || face.width() != image_size {
+            return Err(anyhow::anyhow!(
+                "Skybox face {} is {}x{}, expected {}x{} like the other faces",
+                name,
+                face.width(),
+                face.height(),
+                image_size,
+                image_size,
+            ));
+        }
+    }
+
+    Ok(faces)
+}
+
+fn create_faces_texture(
+    dir: &Path,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+) -> Result<wgpu::Texture, anyhow::Error> {
+    let faces = load_faces(dir)?;
+    let image_size = faces[0].width();
+
+    let max_dimension = device.limits().max_texture_dimension_2d;
+    if image_size > max_dimension {
+        return Err(anyhow::anyhow!(
+            "Skybox faces are {}x{}, but the device only supports textures up to {}x{}",
+            image_size,
+            image_size,
+            max_dimension,
+            max_dimension,
+        ));
+    }
+
+    let size = wgpu::Extent3d {
+        width: image_size,
+        height: image_size,
+        depth_or_array_layers: 6,
+    };
+    let mip_level_count = wgpu::Extent3d {
+        depth_or_array_layers: 1,
+        ..size
+    }
+    .max_mips(wgpu::TextureDimension::D2);
+
+    log::info!(
+        "Copying skybox faces from {} of size {}, {}, 6 with {} mips to gpu",
+        dir.display(),
+        image_size,
+        image_size,
+        mip_level_count,
+    );
+
+    // Build each face's mip chain on the CPU, like entity textures, and lay them out mip major,
+    // like KTX2 does.
+    let chains: Vec<Vec<u8>> = faces
+        .iter()
+        .map(|face| texture::mip_chain(face, true))
+        .collect();
+    let mut image = Vec::with_capacity(chains.iter().map(Vec::len).sum());
+    let mut offset = 0;
+    for level in 0..mip_level_count {
+        let level_size = (image_size >> level).max(1) as usize;
+        let level_bytes = level_size * level_size * 4;
+        for chain in chains.iter() {
+            image.extend_from_slice(&chain[offset..offset + level_bytes]);
+        }
+        offset += level_bytes;
+    }
+
+    Ok(device.create_texture_with_data(
+        queue,
+        &wgpu::TextureDescriptor {
+            size,
+            mip_level_count,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            label: Some("Skybox"),
+            view_formats: &[],
+        },
+        wgpu::util::TextureDataOrder::MipMajor,
+        &image,
+    ))
+}
+
+fn find_face(dir: &Path, name: &str) -> Result<PathBuf, anyhow::Error> {
+    FACE_EXTENSIONS
+        .iter()
+        .map(|extension| dir.join(name).with_extension(extension))
+        .find(|path| path.is_file())
+        .ok_or_else(|| {
+            anyhow::anyhow!(
+                "Skybox face {} not found in {} (tried {})",
+                name,
+                dir.display(),
+                FACE_EXTENSIONS.join(", ")
+            )
+        })
+}
@@ -0,0 +1,154 @@
+use std::path::Path;
+
+use wgpu::util::DeviceExt;
+
+use crate::blackbody;
+
+/// Color of a normal map texel that leaves the surface normal as it is.
+pub const FLAT_NORMAL: [u8; 4] = [128, 128, 255, 255];
+
+/// Upload an image file with a full mip chain. Albedo maps hold sRGB colors, normal maps
+/// `srgb: false` linear vectors.
+pub fn load_texture(
+    path: &Path,
+    srgb: bool,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+) -> Result<wgpu::Texture, anyhow::Error> {
+    let image = image::open(path)
+        .map_err(|e| anyhow::anyhow!("Failed to load {}: {}", path.display(), e))?
+        .to_rgba8();
+
+    let max_dimension = device.limits().max_texture_dimension_2d;
+    if image.width() > max_dimension || image.height() > max_dimension {
+        return Err(anyhow::anyhow!(
+            "{} is {}x{}, but the device only supports textures up to {}x{}",
+            path.display(),
+            image.width(),
+            image.height(),
+            max_dimension,
+            max_dimension,
+        ));
+    }
+
+    let size = wgpu::Extent3d {
+        width: image.width(),
+        height: image.height(),
+        depth_or_array_layers: 1,
+    };
+    let mip_level_count = size.max_mips(wgpu::TextureDimension::D2);
+    log::info!(
+        "Copying {} of size {}, {} with {} mips to gpu",
+        path.display(),
+        size.width,
+        size.height,
+        mip_level_count,
+    );
+
+    let data = mip_chain(&image, srgb);
+    Ok(create_texture(
+        device,
+        queue,
+        size,
+        mip_level_count,
+        srgb,
+        &path.display().to_string(),
+        &data,
+    ))
+}
+
+/// Texels of `image` and all its mip levels, in mip major order. Mips are averaged in linear
+/// color, or `srgb: false` as unit vectors. Averaging the stored bytes would darken albedo and
+/// shorten normals.
+pub fn mip_chain(image: &image::RgbaImage, srgb: bool) -> Vec<u8> {
+    let size = wgpu::Extent3d {
+        width: image.width(),
+        height: image.height(),
+        depth_or_array_layers: 1,
+    };
+    let mut previous = image::Rgba32FImage::from_fn(size.width, size.height, |x, y| {
+        let [r, g, b, a] = image.get_pixel(x, y).0;
+        let alpha = a as f32 / 255.0;
+        image::Rgba(if srgb {
+            let [r, g, b] = [r, g, b].map(blackbody::srgb_to_linear);
+            [r, g, b, alpha]
+        } else {
+            let [x, y, z] = [r, g, b].map(|c| c as f32 / 255.0 * 2.0 - 1.0);
+            [x, y, z, alpha]
+        })
+    });
+
+    let mut data = image.as_raw().clone();
+    for level in 1..size.max_mips(wgpu::TextureDimension::D2) {
+        let level_size = size.mip_level_size(level, wgpu::TextureDimension::D2);
+        // Each level from the one before, big textures would take long from the full size.
+        let mip = image::imageops::resize(
+            &previous,
+            level_size.width,
+            level_size.height,
+            image::imageops::FilterType::Triangle,
+        );
+        for &image::Rgba([r, g, b, a]) in mip.pixels() {
+            let alpha = (a.clamp(0.0, 1.0) * 255.0).round() as u8;
+            let [r, g, b] = if srgb {
+                [r, g, b].map(blackbody::linear_to_srgb)
+            } else {
+                let normal = glam::Vec3::new(r, g, b)
+                    .try_normalize()
+                    .unwrap_or(glam::Vec3::Z);
+                (normal * 0.5 + 0.5)
+                    .to_array()
+                    .map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8)
+            };
+            data.extend_from_slice(&[r, g, b, alpha]);
+        }
+        previous = mip;
+    }
+    data
+}
+
+/// A single texel of `color`, for entities that only have one of the two maps.
+pub fn create_solid_texture(
+    color: [u8; 4],
+    srgb: bool,
+    label: &str,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+) -> wgpu::Texture {
+    let size = wgpu::Extent3d {
+        width: 1,
+        height: 1,
+        depth_or_array_layers: 1,
+    };
+    create_texture(device, queue, size, 1, srgb, label, &color)
+}
+
+fn create_texture(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    size: wgpu::Extent3d,
+    mip_level_count: u32,
+    srgb: bool,
+    label: &str,
+    data: &[u8],
+) -> wgpu::Texture {
+    device.create_texture_with_data(
+        queue,
+        &wgpu::TextureDescriptor {
+            size,
+            mip_level_count,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: if srgb {
+                wgpu::TextureFormat::Rgba8UnormSrgb
+            } else {
+                wgpu::TextureFormat::Rgba8Unorm
+            },
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            label: Some(label),
+            view_formats: &[],
+        },
+        wgpu::util::TextureDataOrder::MipMajor,
+        data,
+    )
+}
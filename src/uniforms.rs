@@ -0,0 +1,67 @@
+// Uniform buffer contents, mirroring the structs of the same name in shader.wgsl.
+// tests/uniforms.rs checks the field offsets against the shader, so a field added on only one
+// side fails there instead of scrambling the uniforms.
+
+use bytemuck::{Pod, Zeroable};
+
+/// Group 0, binding 0.
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+#[repr(C)]
+pub struct Data {
+    pub camera: CameraData,
+    pub black_hole: BlackHoleData,
+    pub lighting: LightingData,
+}
+
+/// Written by [`Camera::to_uniform_data`](crate::camera::Camera::to_uniform_data).
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+#[repr(C)]
+pub struct CameraData {
+    pub proj: [[f32; 4]; 4],
+    pub proj_inv: [[f32; 4]; 4],
+    pub view: [[f32; 4]; 4],
+    pub position: [f32; 4],
+    /// In pixels.
+    pub screen_size: [f32; 2],
+    pub _padding: [f32; 2],
+}
+
+/// Written by [`BlackHoleConfig::to_uniform_data`](crate::config::BlackHoleConfig::to_uniform_data).
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+#[repr(C)]
+pub struct BlackHoleData {
+    pub mass: f32,
+    pub disk_inner_radius: f32,
+    pub disk_outer_radius: f32,
+    pub tolerance: f32,
+    pub max_steps: f32,
+    pub spin: f32,
+    pub inclination: f32,
+    pub metric: f32,
+    pub accretion_rate: f32,
+    // Structs in uniform buffers start on 16 byte boundaries
+    pub _padding: [f32; 3],
+}
+
+/// Written by [`LightingConfig::to_uniform_data`](crate::config::LightingConfig::to_uniform_data).
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+#[repr(C)]
+pub struct LightingData {
+    pub direction: [f32; 3],
+    pub shininess: f32,
+    pub color: [f32; 3],
+    pub specular_strength: f32,
+    pub ambient_color: [f32; 3],
+    pub reflectivity: f32,
+}
+
+/// Group 1, binding 0, one per entity at a dynamic offset.
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+#[repr(C)]
+pub struct EntityData {
+    pub model: [[f32; 4]; 4],
+    /// Inverse transpose of the model matrix's upper 3x3, which keeps normals perpendicular to
+    /// the surface under non-uniform scale. Each column is padded to a vec4, like a WGSL mat3x3.
+    pub normal: [[f32; 4]; 3],
+    pub color: [f32; 4],
+}
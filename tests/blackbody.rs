@@ -0,0 +1,41 @@
+use doodle::blackbody::{
+    blackbody_rgb, lut_temperature, LUT_MAX_TEMPERATURE, LUT_MIN_TEMPERATURE, LUT_SIZE,
+};
+
+#[test]
+fn d65_is_close_to_white() {
+    // The sRGB white point is a 6504 K blackbody, give or take the daylight spectrum's wiggles.
+    let [r, g, b] = blackbody_rgb(6504.0);
+    for channel in [r, g, b] {
+        assert!(channel > 0.9, "{:?}", [r, g, b]);
+    }
+}
+
+#[test]
+fn cool_is_red_and_hot_is_blue() {
+    let [r, g, b] = blackbody_rgb(2000.0);
+    assert!(r > g && g > b, "{:?}", [r, g, b]);
+    assert_eq!(r, 1.0);
+
+    let [r, g, b] = blackbody_rgb(20000.0);
+    assert!(b > g && g > r, "{:?}", [r, g, b]);
+    assert_eq!(b, 1.0);
+}
+
+#[test]
+fn blue_grows_with_temperature() {
+    let mut last = 0.0;
+    for kelvin in (1000..40000).step_by(500) {
+        let [r, _, b] = blackbody_rgb(kelvin as f32);
+        let ratio = b / r;
+        assert!(ratio >= last, "{} K: {} < {}", kelvin, ratio, last);
+        last = ratio;
+    }
+}
+
+#[test]
+fn lut_covers_the_range() {
+    assert_eq!(lut_temperature(0), LUT_MIN_TEMPERATURE);
+    let last = lut_temperature(LUT_SIZE - 1);
+    assert!((last - LUT_MAX_TEMPERATURE).abs() < 1.0, "{}", last);
+}
@@ -0,0 +1,106 @@
+use std::time::Duration;
+
+use clap::Parser;
+use doodle::{
+    clock::{Clock, ClockMode},
+    Cli,
+};
+use web_time::Instant;
+
+fn seconds(s: f64) -> Duration {
+    Duration::from_secs_f64(s)
+}
+
+#[test]
+fn fixed_step_spends_whole_steps_of_real_time() {
+    let mut clock = Clock::new(ClockMode::FixedStep(0.125), 2.0);
+    let start = Instant::now();
+    assert_eq!(clock.tick_at(start).sim, 0.0);
+
+    // Short frames only pile up, until a whole step fits
+    let time = clock.tick_at(start + seconds(0.1));
+    assert_eq!((time.real, time.sim), (0.1, 0.0));
+    let time = clock.tick_at(start + seconds(0.2));
+    assert_eq!(time.sim, 0.125 * 2.0);
+    // A long frame takes several steps at once
+    let time = clock.tick_at(start + seconds(0.45));
+    assert_eq!(time.sim, 2.0 * 0.125 * 2.0);
+    assert_eq!(clock.sim_time(), 0.75);
+}
+
+#[test]
+fn frame_rate_does_not_change_fixed_step_speed() {
+    let run = |frames: u32| {
+        let mut clock = Clock::new(ClockMode::FixedStep(0.01), 1.0);
+        let start = Instant::now();
+        for frame in 0..=frames {
+            clock.tick_at(start + seconds(2.0 * frame as f64 / frames as f64));
+        }
+        clock.sim_time()
+    };
+    assert!((run(120) - 2.0).abs() < 0.011, "{}", run(120));
+    assert!((run(288) - 2.0).abs() < 0.011, "{}", run(288));
+}
+
+#[test]
+fn pause_and_single_step() {
+    let mut clock = Clock::new(ClockMode::FixedStep(0.125), 1.0);
+    let start = Instant::now();
+    clock.tick_at(start);
+    clock.toggle_pause();
+    let time = clock.tick_at(start + seconds(0.2));
+    assert_eq!((time.real, time.sim), (0.2, 0.0));
+
+    clock.step();
+    assert_eq!(clock.tick_at(start + seconds(0.21)).sim, 0.125);
+    assert_eq!(clock.tick_at(start + seconds(0.22)).sim, 0.0);
+    assert!(clock.paused);
+
+    // Time spent paused isn't made up for on resume
+    clock.toggle_pause();
+    assert_eq!(clock.tick_at(start + seconds(0.23)).sim, 0.0);
+    assert_eq!(clock.sim_time(), 0.125);
+}
+
+#[test]
+fn per_frame_ignores_the_wall_clock() {
+    let mut clock = Clock::new(ClockMode::PerFrame(0.5), -1.0);
+    let start = Instant::now();
+    for frame in 0..4 {
+        let time = clock.tick_at(start + seconds(frame as f64 * 3.0));
+        assert_eq!((time.real, time.sim), (0.5, -0.5));
+    }
+    assert_eq!(clock.sim_time(), -2.0);
+}
+
+#[test]
+fn time_scale_stays_recoverable() {
+    let mut clock = Clock::new(ClockMode::RealTime, 0.0);
+    assert!(clock.time_scale > 0.0);
+    for _ in 0..200 {
+        clock.slow_down();
+    }
+    assert!(clock.time_scale > 0.0);
+    clock.speed_up();
+    assert!(clock.time_scale > 0.0);
+
+    clock.reverse();
+    for _ in 0..200 {
+        clock.speed_up();
+    }
+    assert!(clock.time_scale.is_finite() && clock.time_scale < 0.0);
+}
+
+#[test]
+fn fixed_step_must_be_positive() {
+    for step in ["0", "-0.5", "inf", "NaN"] {
+        let error = Cli::try_parse_from(["doodle", &format!("--fixed-step={step}")]).unwrap_err();
+        assert!(
+            error.to_string().contains("must be a positive"),
+            "{}",
+            error
+        );
+    }
+    let args = Cli::try_parse_from(["doodle", "--headless", "--fixed-step", "0.25"]).unwrap();
+    assert_eq!(Clock::from_args(&args).mode, ClockMode::PerFrame(0.25));
+}
@@ -0,0 +1,82 @@
+use doodle::config::Config;
+
+fn parse_error(text: &str) -> String {
+    Config::parse(text).unwrap_err().to_string()
+}
+
+#[test]
+fn unknown_keys_are_named() {
+    let error = parse_error("[camera]\ndistance = 100.0\nzoom = 2.0\n");
+    assert!(error.starts_with("`camera.zoom` (line 3)"), "{}", error);
+
+    let error = parse_error(
+        "[[entities]]\n\
+         mesh = { type = \"sphere\", size = 1.0 }\n",
+    );
+    assert!(
+        error.starts_with("`entities[0].mesh.size` (line 2)"),
+        "{}",
+        error
+    );
+}
+
+#[test]
+fn wrong_types_are_named() {
+    let error = parse_error(
+        "[[entities]]\n\
+         name = \"planet\"\n\
+         mesh = { type = \"sphere\", radius = \"big\" }\n",
+    );
+    assert!(
+        error.starts_with("`entities[0].mesh.radius` (line 3)"),
+        "{}",
+        error
+    );
+    assert!(error.contains("expected f32"), "{}", error);
+}
+
+#[test]
+fn out_of_range_values_are_named() {
+    let error = parse_error(
+        "[[entities]]\n\
+         mesh = { type = \"sphere\" }\n\
+         [[entities]]\n\
+         mesh = { type = \"icosphere\", radius = -1.0 }\n",
+    );
+    assert_eq!(error, "`entities[1].mesh.radius`: must be positive");
+}
+
+#[test]
+fn mesh_keys_must_match_the_type() {
+    let error = parse_error(
+        "[[entities]]\n\
+         mesh = { type = \"icosphere\", stacks = 3 }\n",
+    );
+    assert!(
+        error.starts_with("`entities[0].mesh` (line 2)"),
+        "{}",
+        error
+    );
+    assert!(error.contains("`stacks`"), "{}", error);
+}
+
+#[test]
+fn disk_fits_outside_the_isco_of_both_metrics() {
+    // Retrograde Kerr orbits are only stable from 9 M, past this disk
+    let error = parse_error(
+        "[black_hole]\n\
+         metric = \"schwarzschild\"\n\
+         mass = 1.0\n\
+         spin = -1.0\n\
+         disk_outer_radius = 8.0\n",
+    );
+    assert!(
+        error.starts_with("`black_hole.disk_outer_radius`"),
+        "{}",
+        error
+    );
+    assert!(error.contains("Kerr"), "{}", error);
+    assert!(
+        Config::parse("[black_hole]\nmass = 1.0\nspin = 0.0\ndisk_outer_radius = 8.0\n").is_ok()
+    );
+}
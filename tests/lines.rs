@@ -0,0 +1,33 @@
+use doodle::lines::{push_line, LinePoint, LineVertex, Trail};
+use glam::{Vec3, Vec4};
+
+#[test]
+fn trail_keeps_its_duration_and_rewinds() {
+    let mut trail = Trail::new(2.0);
+    for i in 0..=100 {
+        let time = i as f64 * 0.1;
+        trail.record(time, Vec3::new(time as f32, 0.0, 0.0));
+    }
+    let points = trail.points(10.0, Vec3::new(10.0, 0.0, 0.0), Vec4::ONE);
+    assert!(points.first().unwrap().position.x >= 8.0 - 1e-4);
+    assert_eq!(points.last().unwrap().color, Vec4::ONE);
+    assert!(points.first().unwrap().color.w < 0.01);
+
+    // Running backwards drops what happened after
+    trail.record(9.0, Vec3::new(9.0, 0.0, 0.0));
+    let points = trail.points(9.0, Vec3::new(9.0, 0.0, 0.0), Vec4::ONE);
+    assert!(points.iter().all(|point| point.position.x <= 9.0 + 1e-4));
+}
+
+#[test]
+fn lines_are_a_quad_per_segment() {
+    let point = |x: f32| LinePoint {
+        position: Vec3::new(x, 0.0, 0.0),
+        color: Vec4::ONE,
+    };
+    let mut vertices: Vec<LineVertex> = Vec::new();
+    push_line(&mut vertices, &[point(0.0)], 2.0);
+    assert!(vertices.is_empty());
+    push_line(&mut vertices, &[point(0.0), point(1.0), point(2.0)], 2.0);
+    assert_eq!(vertices.len(), 12);
+}
@@ -0,0 +1,302 @@
+use std::collections::HashMap;
+
+use doodle::{
+    generate_cube_sphere, generate_icosphere, generate_sphere,
+    model::{generate_tangents, load_obj, Mesh},
+    TexturedVertex,
+};
+use glam::Vec3;
+
+fn meshes() -> Vec<(String, Vec<TexturedVertex>, Vec<u32>)> {
+    let mut meshes = Vec::new();
+    for subdivisions in 0..4 {
+        let (vertices, indices) = generate_icosphere(Some(2.0), Some(subdivisions));
+        meshes.push((format!("icosphere {}", subdivisions), vertices, indices));
+    }
+    for resolution in [1, 2, 5, 16] {
+        let (vertices, indices) = generate_cube_sphere(Some(2.0), Some(resolution));
+        meshes.push((format!("cube sphere {}", resolution), vertices, indices));
+    }
+    meshes
+}
+
+#[test]
+fn normals_are_unit_length_and_point_outwards() {
+    for (name, vertices, _) in meshes() {
+        for vertex in &vertices {
+            let normal = Vec3::from(vertex.normal);
+            let pos = Vec3::from(vertex.pos);
+            assert!(
+                (normal.length() - 1.0).abs() < 1e-5,
+                "{}: normal {}",
+                name,
+                normal
+            );
+            assert!(
+                (pos - 2.0 * normal).length() < 1e-5,
+                "{}: {} at {}",
+                name,
+                normal,
+                pos
+            );
+        }
+    }
+}
+
+/// Index buffer with every vertex replaced by the first one at the same position, joining up the
+/// copies made for texture seams. Also returns how many distinct positions there are.
+fn weld(vertices: &[TexturedVertex], indices: &[u32]) -> (Vec<u32>, usize) {
+    let mut first: HashMap<[u32; 3], u32> = HashMap::new();
+    let welded = indices
+        .iter()
+        .map(|&i| {
+            let pos = vertices[i as usize].pos.map(f32::to_bits);
+            *first.entry(pos).or_insert(i)
+        })
+        .collect();
+    (welded, first.len())
+}
+
+#[test]
+fn surfaces_are_watertight() {
+    for (name, vertices, indices) in meshes() {
+        assert_eq!(indices.len() % 3, 0, "{}", name);
+        assert!(
+            indices.iter().all(|&i| (i as usize) < vertices.len()),
+            "{}",
+            name
+        );
+        let (indices, positions) = weld(&vertices, &indices);
+
+        // Closed and consistently wound: every edge is used once in each direction
+        let mut edges: HashMap<(u32, u32), u32> = HashMap::new();
+        for triangle in indices.chunks(3) {
+            assert!(
+                triangle[0] != triangle[1]
+                    && triangle[1] != triangle[2]
+                    && triangle[2] != triangle[0],
+                "{}: degenerate triangle {:?}",
+                name,
+                triangle
+            );
+            for k in 0..3 {
+                *edges
+                    .entry((triangle[k], triangle[(k + 1) % 3]))
+                    .or_default() += 1;
+            }
+        }
+        for (&(a, b), &count) in &edges {
+            assert_eq!(count, 1, "{}: edge {}-{} used {} times", name, a, b, count);
+            assert_eq!(
+                edges.get(&(b, a)),
+                Some(&1),
+                "{}: edge {}-{} is open",
+                name,
+                a,
+                b
+            );
+        }
+
+        // No stray vertices either, or the Euler characteristic of a sphere would be off
+        let faces = indices.len() / 3;
+        let euler = positions as i64 - (edges.len() / 2) as i64 + faces as i64;
+        assert_eq!(euler, 2, "{}", name);
+    }
+}
+
+#[test]
+fn winding_matches_the_uv_sphere() {
+    let winding = |vertices: &[TexturedVertex], triangle: &[u32]| {
+        let [a, b, c] = [0, 1, 2].map(|k| Vec3::from(vertices[triangle[k] as usize].pos));
+        (b - a).cross(c - a).dot(a + b + c).signum()
+    };
+
+    // The UV sphere's first stack has degenerate triangles at the pole
+    let (vertices, indices) = generate_sphere(None, None, None);
+    let expected = winding(&vertices, &indices[indices.len() / 2..][..3]);
+
+    for (name, vertices, indices) in meshes() {
+        for triangle in indices.chunks(3) {
+            assert_eq!(
+                winding(&vertices, triangle),
+                expected,
+                "{}: {:?}",
+                name,
+                triangle
+            );
+        }
+    }
+}
+
+#[test]
+fn uv_sphere_tangents_follow_the_texture() {
+    let (vertices, indices) = generate_sphere(Some(2.0), Some(32), Some(64));
+    let mut generated = vertices.clone();
+    generate_tangents(&mut generated, &indices);
+
+    for (vertex, generated) in vertices.iter().zip(&generated) {
+        let normal = Vec3::from(vertex.normal);
+        let tangent = Vec3::from_slice(&vertex.tangent[..3]);
+        assert!((tangent.length() - 1.0).abs() < 1e-5, "{:?}", vertex);
+        assert!(tangent.dot(normal).abs() < 1e-5, "{:?}", vertex);
+
+        // The poles' triangles collapse, and skew what's derived on the rings next to them
+        if normal.y.abs() > 0.95 {
+            continue;
+        }
+        let bitangent = vertex.tangent[3] * normal.cross(tangent);
+        assert!(
+            bitangent.y > 0.0,
+            "bitangent {} should point north",
+            bitangent
+        );
+        assert!(
+            Vec3::from_slice(&generated.tangent[..3]).dot(tangent) > 0.99,
+            "{:?} generated as {:?}",
+            vertex,
+            generated
+        );
+        assert_eq!(generated.tangent[3], vertex.tangent[3], "{:?}", vertex);
+    }
+}
+
+#[test]
+fn sphere_textures_are_continuous_and_upright() {
+    // A bare icosahedron's triangles span up to half the longitude, too coarse to follow it
+    for (name, vertices, indices) in meshes().into_iter().skip(1) {
+        for triangle in indices.chunks(3) {
+            let corners = [0, 1, 2].map(|k| vertices[triangle[k] as usize]);
+            let uvs = corners.map(|corner| glam::Vec2::from(corner.uv));
+            // Across the icosphere's seam a triangle would smear most of the texture over itself,
+            // cube sphere cells may cover all of it at the lowest resolution
+            let span = uvs.iter().fold(0.0f32, |span, a| {
+                uvs.iter()
+                    .map(|b| (*a - *b).abs().max_element())
+                    .fold(span, f32::max)
+            });
+            let max_span = if name.starts_with("icosphere") {
+                0.5
+            } else {
+                1.0
+            };
+            assert!(span <= max_span, "{}: {:?}", name, uvs);
+
+            // Not mirrored: the texture's right and up match each corner's tangent frame
+            let mut derived = corners;
+            generate_tangents(&mut derived, &[0, 1, 2]);
+            for (corner, derived) in corners.iter().zip(derived) {
+                let normal = Vec3::from(corner.normal);
+                let tangent = Vec3::from_slice(&corner.tangent[..3]);
+                assert!(
+                    (tangent.length() - 1.0).abs() < 1e-4,
+                    "{}: {:?}",
+                    name,
+                    corner
+                );
+                assert!(tangent.dot(normal).abs() < 1e-4, "{}: {:?}", name, corner);
+                assert!(
+                    Vec3::from_slice(&derived.tangent[..3]).dot(tangent) > 0.5,
+                    "{}: {:?} in a triangle going {:?}",
+                    name,
+                    corner,
+                    derived
+                );
+                assert_eq!(
+                    derived.tangent[3], corner.tangent[3],
+                    "{}: {:?}",
+                    name, corner
+                );
+            }
+        }
+    }
+}
+
+/// Load `source` as an OBJ file.
+fn load_obj_source(name: &str, source: &str) -> Vec<Mesh> {
+    let path = std::env::temp_dir().join(format!("doodle-{}-{}.obj", name, std::process::id()));
+    std::fs::write(&path, source).unwrap();
+    let meshes = load_obj(&path);
+    std::fs::remove_file(&path).unwrap();
+    meshes.unwrap()
+}
+
+#[test]
+fn obj_tangents_point_along_u() {
+    // A quad facing +z, with the texture upright on it
+    let meshes = load_obj_source(
+        "quad",
+        "v -1 -1 0\nv 1 -1 0\nv 1 1 0\nv -1 1 0\n\
+         vt 0 0\nvt 1 0\nvt 1 1\nvt 0 1\n\
+         vn 0 0 1\n\
+         f 1/1/1 2/2/1 3/3/1 4/4/1\n",
+    );
+
+    assert!(meshes[0].has_uvs);
+    for vertex in &meshes[0].vertices {
+        assert_eq!(vertex.tangent, [1.0, 0.0, 0.0, 1.0], "{:?}", vertex);
+        // OBJ puts v = 0 at the bottom of the image
+        let expected_v = if vertex.pos[1] > 0.0 { 0.0 } else { 1.0 };
+        assert_eq!(vertex.uv[1], expected_v, "{:?}", vertex);
+    }
+}
+
+#[test]
+fn obj_polygons_are_triangulated() {
+    // A pentagon and a quad next to it
+    let meshes = load_obj_source(
+        "polygons",
+        "v 0 0 0\nv 2 0 0\nv 3 1 0\nv 1 2 0\nv -1 1 0\nv 4 0 0\nv 4 1 0\n\
+         f 1 2 3 4 5\n\
+         f 2 6 7 3\n",
+    );
+    assert_eq!(meshes.len(), 1);
+    assert_eq!(meshes[0].indices.len(), (3 + 2) * 3);
+    assert_eq!(meshes[0].vertices.len(), 7);
+    assert!(!meshes[0].has_uvs);
+}
+
+#[test]
+fn obj_normals_are_shared_across_texture_seams() {
+    // A roof with its ridge along x, without normals. The two slopes use separate texture
+    // coordinates, so the ridge vertices are split. Both fan out from the same corner.
+    let meshes = load_obj_source(
+        "roof",
+        "v 0 1 0\nv 1 1 0\nv 0 0 1\nv 1 0 1\nv 0 0 -1\nv 1 0 -1\n\
+         vt 0 0\nvt 1 0\nvt 0 1\nvt 1 1\nvt 0 0.5\nvt 1 0.5\n\
+         f 3/1 4/2 2/4 1/3\n\
+         f 2/6 6/2 5/1 1/5\n",
+    );
+    let vertices = &meshes[0].vertices;
+    for vertex in vertices {
+        let normal = Vec3::from(vertex.normal);
+        assert!((normal.length() - 1.0).abs() < 1e-5, "{:?}", vertex);
+    }
+    let ridge: Vec<_> = vertices.iter().filter(|v| v.pos[1] == 1.0).collect();
+    assert_eq!(ridge.len(), 4);
+    for vertex in ridge {
+        assert!(
+            Vec3::from(vertex.normal).abs_diff_eq(Vec3::Y, 1e-5),
+            "{:?}",
+            vertex
+        );
+    }
+}
+
+#[test]
+fn obj_objects_and_groups_are_separate_meshes() {
+    let meshes = load_obj_source(
+        "groups",
+        "v 0 0 0\nv 1 0 0\nv 0 1 0\nv 1 1 0\n\
+         o first\n\
+         f 1 2 3\n\
+         o second\n\
+         g top\n\
+         f 2 4 3\n\
+         g bottom\n\
+         f 1 2 4 3\n",
+    );
+    let names: Vec<_> = meshes.iter().map(|mesh| mesh.name.as_str()).collect();
+    let triangles: Vec<_> = meshes.iter().map(|mesh| mesh.indices.len() / 3).collect();
+    assert_eq!(names, ["first/default", "second/top", "second/bottom"]);
+    assert_eq!(triangles, [1, 1, 2]);
+}
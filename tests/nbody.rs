@@ -0,0 +1,152 @@
+use glam::DVec3;
+
+use doodle::nbody::{Body, Integrator, Prediction, Simulation};
+
+/// A light body on a circular orbit of radius 10 around a unit central mass.
+fn circular_orbit(integrator: Integrator) -> Simulation {
+    let mut simulation = Simulation::new(integrator, 0.01);
+    simulation.central_mass = 1.0;
+    simulation.add_body(Body {
+        mass: 1e-3,
+        position: DVec3::new(10.0, 0.0, 0.0),
+        velocity: DVec3::new(0.0, 0.0, (1.0f64 / 10.0).sqrt()),
+    });
+    simulation
+}
+
+// 2π sqrt(r³ / GM)
+const PERIOD: f64 = 198.69176531642947;
+
+/// Advance a second at a time, like frames would.
+fn run(simulation: &mut Simulation, duration: f64) {
+    let mut time = 0.0;
+    while time < duration.abs() {
+        let dt = (duration.abs() - time).min(1.0);
+        simulation.advance(dt.copysign(duration));
+        time += dt;
+    }
+}
+
+#[test]
+fn circular_orbit_closes() {
+    for integrator in [Integrator::Leapfrog, Integrator::Yoshida] {
+        let mut simulation = circular_orbit(integrator);
+        run(&mut simulation, PERIOD);
+
+        let position = simulation.bodies()[0].position;
+        assert!(
+            (position - DVec3::new(10.0, 0.0, 0.0)).length() < 0.05,
+            "{:?} ends up at {}",
+            integrator,
+            position
+        );
+        assert!(
+            (position.length() - 10.0).abs() < 1e-3,
+            "{:?} radius",
+            integrator
+        );
+        let drift = simulation.drift_since_start();
+        assert!(drift.energy.abs() < 1e-6, "{:?} {:?}", integrator, drift);
+        assert!(
+            drift.angular_momentum < 1e-9,
+            "{:?} {:?}",
+            integrator,
+            drift
+        );
+    }
+}
+
+#[test]
+fn higher_order_is_more_accurate() {
+    let error = |integrator| {
+        let mut simulation = circular_orbit(integrator);
+        run(&mut simulation, PERIOD);
+        (simulation.bodies()[0].position.length() - 10.0).abs()
+    };
+    assert!(error(Integrator::Euler) > error(Integrator::Leapfrog));
+    assert!(error(Integrator::Leapfrog) > error(Integrator::Yoshida));
+}
+
+#[test]
+fn running_backwards_returns_to_the_start() {
+    let mut simulation = Simulation::new(Integrator::Leapfrog, 0.01);
+    simulation.softening = 0.1;
+    let start = [
+        Body {
+            mass: 1.0,
+            position: DVec3::new(-1.0, 0.0, 0.0),
+            velocity: DVec3::new(0.0, 0.3, 0.0),
+        },
+        Body {
+            mass: 1.0,
+            position: DVec3::new(1.0, 0.0, 0.0),
+            velocity: DVec3::new(0.0, -0.3, 0.1),
+        },
+        Body {
+            mass: 0.5,
+            position: DVec3::new(0.0, 2.0, 0.5),
+            velocity: DVec3::new(-0.2, 0.0, 0.0),
+        },
+    ];
+    for body in start {
+        simulation.add_body(body);
+    }
+
+    run(&mut simulation, 20.0);
+    // Without a central mass nothing pulls from outside
+    assert!(simulation.drift_since_start().momentum < 1e-12);
+
+    run(&mut simulation, -20.0);
+    for (body, start) in simulation.bodies().iter().zip(start) {
+        assert!((body.position - start.position).length() < 1e-9);
+        assert!((body.velocity - start.velocity).length() < 1e-9);
+    }
+}
+
+#[test]
+fn falling_behind_keeps_the_time() {
+    let mut behind = circular_orbit(Integrator::Leapfrog);
+    let mut reference = circular_orbit(Integrator::Leapfrog);
+    run(&mut reference, 150.0);
+
+    // More steps than one call takes, the rest is stepped through on later calls
+    behind.advance(150.0);
+    assert!(behind.lag() > 1.0);
+    while behind.lag() >= behind.time_step {
+        behind.advance(0.0);
+    }
+    let (a, b) = (behind.bodies()[0], reference.bodies()[0]);
+    assert!((a.position - b.position).length() < 1e-9);
+}
+
+#[test]
+fn prediction_matches_the_simulation() {
+    let mut simulation = circular_orbit(Integrator::Leapfrog);
+    let mut prediction = Prediction::new(&simulation, 0.5, false);
+    prediction.update(&simulation, 10.0, 0.5, false);
+    let paths = prediction.paths(&simulation);
+    assert_eq!(paths.len(), 1);
+    assert_eq!(paths[0].len(), 21);
+    // The prediction runs on a copy
+    assert_eq!(simulation.bodies()[0].position, DVec3::new(10.0, 0.0, 0.0));
+
+    // Catching up only extends the path, which ends up where predicting from scratch would
+    run(&mut simulation, 2.5);
+    prediction.update(&simulation, 10.0, 0.5, false);
+    let mut fresh = Prediction::new(&simulation, 0.5, false);
+    fresh.update(&simulation, 10.0, 0.5, false);
+    assert_eq!(prediction.paths(&simulation), fresh.paths(&simulation));
+
+    run(&mut simulation, 7.5);
+    assert!((paths[0][20] - simulation.bodies()[0].position).length() < 1e-9);
+}
+
+#[test]
+fn backwards_prediction_retraces_the_path() {
+    let mut simulation = circular_orbit(Integrator::Leapfrog);
+    run(&mut simulation, 10.0);
+    let mut prediction = Prediction::new(&simulation, 0.5, true);
+    prediction.update(&simulation, 10.0, 0.5, true);
+    let path = &prediction.paths(&simulation)[0];
+    assert!((path[20] - DVec3::new(10.0, 0.0, 0.0)).length() < 1e-9);
+}
@@ -0,0 +1,109 @@
+use std::f64::consts::{FRAC_PI_2, TAU};
+
+use clap::Parser;
+use doodle::{
+    config::Config,
+    orbit::{solve_kepler, KeplerOrbit},
+    Cli,
+};
+use glam::DVec3;
+
+fn orbit(eccentricity: f64) -> KeplerOrbit {
+    KeplerOrbit {
+        semi_major_axis: 10.0,
+        eccentricity,
+        inclination: 0.0,
+        longitude_of_ascending_node: 0.0,
+        argument_of_periapsis: 0.0,
+        mean_anomaly_at_epoch: 0.0,
+        period: 8.0,
+    }
+}
+
+#[test]
+fn kepler_solution_satisfies_the_equation() {
+    for eccentricity in [0.0, 0.3, 0.7, 0.95, 0.999] {
+        for i in 0..100 {
+            let mean_anomaly = i as f64 * TAU / 100.0;
+            let e = solve_kepler(mean_anomaly, eccentricity);
+            let residual = e - eccentricity * e.sin() - mean_anomaly;
+            assert!(
+                residual.abs() < 1e-10,
+                "M = {}, e = {}: residual {}",
+                mean_anomaly,
+                eccentricity,
+                residual
+            );
+        }
+    }
+}
+
+#[test]
+fn ellipse_has_the_right_apsides() {
+    let orbit = orbit(0.5);
+    let periapsis = orbit.position(0.0);
+    let apoapsis = orbit.position(0.5 * orbit.period);
+    assert!((periapsis - DVec3::new(5.0, 0.0, 0.0)).length() < 1e-9);
+    assert!((apoapsis - DVec3::new(-15.0, 0.0, 0.0)).length() < 1e-9);
+    assert!((orbit.position(orbit.period) - periapsis).length() < 1e-9);
+}
+
+#[test]
+fn prograde_orbits_turn_like_the_disk() {
+    // Counterclockwise seen from +y, which is the direction of positive spin
+    let orbit = orbit(0.0);
+    let quarter = orbit.position(0.25 * orbit.period);
+    assert!((quarter - DVec3::new(0.0, 0.0, -10.0)).length() < 1e-9);
+}
+
+#[test]
+fn inclination_tilts_around_the_ascending_node() {
+    let mut orbit = orbit(0.0);
+    orbit.inclination = FRAC_PI_2;
+    orbit.longitude_of_ascending_node = FRAC_PI_2;
+    // The node turns from +x to -z, and a quarter lap later the polar orbit passes overhead
+    assert!((orbit.position(0.0) - DVec3::new(0.0, 0.0, -10.0)).length() < 1e-9);
+    let quarter = orbit.position(0.25 * orbit.period);
+    assert!((quarter - DVec3::new(0.0, 10.0, 0.0)).length() < 1e-9);
+}
+
+#[test]
+fn period_follows_from_the_parent_mass() {
+    let config = Config::parse(
+        "[black_hole]\n\
+         mass = 2.0\n\
+         [simulation]\n\
+         gravitational_constant = 50.0\n\
+         [[entities]]\n\
+         name = \"planet\"\n\
+         mesh = { type = \"sphere\" }\n\
+         body = { mass = 4.0 }\n\
+         [[entities]]\n\
+         parent = \"planet\"\n\
+         mesh = { type = \"sphere\" }\n\
+         orbit = { semi_major_axis = 10.0 }\n",
+    )
+    .unwrap();
+    let orbit = config.create_orbit(1).unwrap();
+    assert!((orbit.period - TAU * (1000.0f64 / 200.0).sqrt()).abs() < 1e-9);
+    assert_eq!(config.parent_index(1), Some(0));
+}
+
+#[test]
+fn entities_spin_without_an_orbit() {
+    let config = Config::from_cli(&Cli::parse_from(["doodle"])).unwrap();
+    let period = config.entities[0].rotation_period.unwrap();
+    assert!((TAU / period - 0.018).abs() < 1e-9);
+
+    let config = Config::parse(
+        "[[entities]]\n\
+         mesh = { type = \"sphere\" }\n\
+         rotation_period = -5.0\n",
+    )
+    .unwrap();
+    assert_eq!(config.entities[0].rotation_period, Some(-5.0));
+    assert!(
+        Config::parse("[[entities]]\nmesh = { type = \"sphere\" }\nrotation_period = 0.0\n")
+            .is_err()
+    );
+}
@@ -0,0 +1,146 @@
+use std::path::{Path, PathBuf};
+
+use clap::Parser;
+use doodle::{
+    config::Config,
+    framework::start_headless,
+    reference::{self, CubeMap},
+    scene::Scene,
+    skybox::FACE_NAMES,
+    Cli,
+};
+
+const WIDTH: u32 = 160;
+const HEIGHT: u32 = 90;
+
+/// A fresh directory with a scene file using `black_hole`, and a smooth skybox that is nowhere black.
+fn write_scene(name: &str, black_hole: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("doodle-{}-{}", name, std::process::id()));
+    std::fs::create_dir_all(dir.join("sky")).unwrap();
+
+    for (i, face) in FACE_NAMES.iter().enumerate() {
+        let image = image::RgbaImage::from_fn(64, 64, |x, y| {
+            let base = 40 + 30 * i as u32;
+            image::Rgba([
+                (base + 2 * x) as u8,
+                (base + 2 * y) as u8,
+                230 - base as u8,
+                255,
+            ])
+        });
+        image
+            .save(dir.join("sky").join(face).with_extension("png"))
+            .unwrap();
+    }
+
+    let scene = format!(
+        "skybox = \"sky\"\n\
+         [camera]\n\
+         distance = 150.0\n\
+         yaw = -143.0\n\
+         pitch = 10.0\n\
+         target = [0.0, 0.0, 0.0]\n\
+         [black_hole]\n\
+         {}\n",
+        black_hole
+    );
+    std::fs::write(dir.join("scene.toml"), scene).unwrap();
+    dir
+}
+
+fn render_gpu(dir: &Path) -> image::RgbaImage {
+    let output = dir.join("gpu.png");
+    let args = Cli::parse_from([
+        "doodle".as_ref(),
+        "--headless".as_ref(),
+        "--scene".as_ref(),
+        dir.join("scene.toml").as_os_str(),
+        "--output".as_ref(),
+        output.as_os_str(),
+        "--width".as_ref(),
+        WIDTH.to_string().as_ref(),
+        "--height".as_ref(),
+        HEIGHT.to_string().as_ref(),
+    ]);
+    pollster::block_on(start_headless::<Scene>(args)).unwrap();
+    image::open(output).unwrap().to_rgba8()
+}
+
+fn render_cpu(dir: &Path) -> image::RgbaImage {
+    let config = Config::load(&dir.join("scene.toml")).unwrap();
+    let camera = config.camera.create_camera((WIDTH, HEIGHT));
+    let sky = CubeMap::load(&dir.join("sky")).unwrap();
+    reference::render(&camera, &config.black_hole, &sky)
+}
+
+/// Both renders may only disagree on a few pixels, where rays skim the photon sphere and the
+/// float rounding of the two implementations sends them different ways.
+fn assert_renders_match(name: &str, black_hole: &str) {
+    let dir = write_scene(name, black_hole);
+    let gpu = render_gpu(&dir);
+    let cpu = render_cpu(&dir);
+    assert_eq!(gpu.dimensions(), cpu.dimensions());
+
+    let differences: Vec<u8> = gpu
+        .pixels()
+        .zip(cpu.pixels())
+        .map(|(a, b)| (0..3).map(|c| a[c].abs_diff(b[c])).max().unwrap())
+        .collect();
+    let mean = differences.iter().map(|&d| d as f32).sum::<f32>() / differences.len() as f32;
+    let outliers = differences.iter().filter(|&&d| d > 16).count();
+
+    cpu.save(dir.join("cpu.png")).unwrap();
+    assert!(
+        mean < 1.0 && outliers * 100 < differences.len(),
+        "mean difference {}, {} of {} pixels off by more than 16, see {}",
+        mean,
+        outliers,
+        differences.len(),
+        dir.display()
+    );
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn schwarzschild_matches_the_shader() {
+    assert_renders_match("schwarzschild", "metric = \"schwarzschild\"");
+}
+
+#[test]
+fn kerr_matches_the_shader() {
+    assert_renders_match(
+        "kerr",
+        "metric = \"kerr\"\nspin = 0.9\ninclination = 20.0\naccretion_rate = 2.0",
+    );
+}
+
+#[test]
+fn schwarzschild_shadow_has_the_critical_size() {
+    // Without a disk in the way
+    let dir = write_scene(
+        "shadow",
+        "mass = 2.5\ndisk_inner_radius = 2000.0\ndisk_outer_radius = 2001.0",
+    );
+    let image = render_cpu(&dir);
+    std::fs::remove_dir_all(dir).unwrap();
+
+    let black = (0..WIDTH)
+        .filter(|&x| {
+            image.get_pixel(x, HEIGHT / 2).0[..3]
+                .iter()
+                .all(|&c| c == 0)
+        })
+        .count() as f32;
+
+    // Rays passing closer than 3√3 M fall in. They start along coordinate directions, so the
+    // shadow is the cone through that impact parameter.
+    let angle = (3.0 * 3f32.sqrt() * 2.5 / 150.0).asin();
+    let pixels_per_tangent = HEIGHT as f32 / (2.0 * std::f32::consts::FRAC_PI_8.tan());
+    let expected = 2.0 * angle.tan() * pixels_per_tangent;
+    assert!(
+        (black - expected).abs() <= 2.0,
+        "shadow is {} pixels wide, expected {}",
+        black,
+        expected
+    );
+}
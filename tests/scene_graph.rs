@@ -0,0 +1,113 @@
+use doodle::{
+    config::Config,
+    scene_graph::{NodeId, SceneGraph},
+    Transform,
+};
+use glam::{Quat, Vec3};
+
+fn assert_near(a: Vec3, b: Vec3) {
+    assert!((a - b).length() < 1e-5, "{} != {}", a, b);
+}
+
+/// A star with a planet ten units out, turned a quarter around y, and a moon two units out from the planet.
+fn solar_system() -> (SceneGraph, NodeId, NodeId, NodeId) {
+    let mut graph = SceneGraph::new();
+    let star = graph.add_node(
+        Some("star".to_string()),
+        None,
+        Transform::from_position(Vec3::new(100.0, 0.0, 0.0)),
+    );
+    let planet = graph.add_node(
+        Some("planet".to_string()),
+        Some(star),
+        Transform {
+            position: Vec3::new(10.0, 0.0, 0.0),
+            rotation: Quat::from_rotation_y(std::f32::consts::FRAC_PI_2),
+            scale: Vec3::ONE,
+        },
+    );
+    let moon = graph.add_node(
+        None,
+        Some(planet),
+        Transform::from_position(Vec3::new(2.0, 0.0, 0.0)),
+    );
+    (graph, star, planet, moon)
+}
+
+#[test]
+fn children_inherit_their_parents_transform() {
+    let (mut graph, star, _, moon) = solar_system();
+    assert_near(graph.world_position(moon), Vec3::new(110.0, 0.0, -2.0));
+
+    graph.local_mut(star).position = Vec3::ZERO;
+    // Only after the update
+    assert_near(graph.world_position(moon), Vec3::new(110.0, 0.0, -2.0));
+    graph.update();
+    assert_near(graph.world_position(moon), Vec3::new(10.0, 0.0, -2.0));
+}
+
+#[test]
+fn reparenting_keeps_the_local_or_world_transform() {
+    let (mut graph, star, planet, moon) = solar_system();
+
+    graph.reparent(moon, Some(star)).unwrap();
+    graph.update();
+    assert_eq!(graph.parent(moon), Some(star));
+    assert_eq!(graph.children(planet), &[] as &[NodeId]);
+    assert_near(graph.world_position(moon), Vec3::new(102.0, 0.0, 0.0));
+
+    graph.reparent_in_place(moon, Some(planet)).unwrap();
+    graph.update();
+    assert_near(graph.world_position(moon), Vec3::new(102.0, 0.0, 0.0));
+    assert_near(graph.local(moon).position, Vec3::new(0.0, 0.0, -8.0));
+
+    graph.reparent(moon, None).unwrap();
+    graph.update();
+    assert_eq!(graph.find("star"), Some(star));
+    assert_eq!(graph.parent(moon), None);
+}
+
+#[test]
+fn world_positions_go_through_the_parents() {
+    let (mut graph, star, planet, moon) = solar_system();
+    graph.set_world_position(moon, Vec3::new(0.0, 5.0, 0.0));
+    graph.update();
+    assert_near(graph.world_position(moon), Vec3::new(0.0, 5.0, 0.0));
+
+    // Parents that moved since the last update count too
+    graph.local_mut(star).position = Vec3::ZERO;
+    graph.local_mut(planet).rotation = Quat::IDENTITY;
+    graph.set_world_position(moon, Vec3::new(0.0, 5.0, 0.0));
+    graph.update();
+    assert_near(graph.world_position(moon), Vec3::new(0.0, 5.0, 0.0));
+    assert_near(graph.local(moon).position, Vec3::new(-10.0, 5.0, 0.0));
+}
+
+#[test]
+fn nodes_cannot_move_below_their_descendants() {
+    let (mut graph, star, _, moon) = solar_system();
+    assert!(graph.reparent(star, Some(moon)).is_err());
+    assert!(graph.reparent(star, Some(star)).is_err());
+    assert_eq!(graph.parent(star), None);
+}
+
+#[test]
+fn parent_cycles_are_rejected() {
+    let error = Config::parse(
+        "[[entities]]\n\
+         name = \"a\"\n\
+         parent = \"b\"\n\
+         mesh = { type = \"sphere\" }\n\
+         orbit = { semi_major_axis = 1.0, period = 1.0 }\n\
+         [[entities]]\n\
+         name = \"b\"\n\
+         parent = \"a\"\n\
+         mesh = { type = \"sphere\" }\n",
+    )
+    .unwrap_err();
+    assert!(
+        error.to_string().contains("entities[0].parent"),
+        "{}",
+        error
+    );
+}
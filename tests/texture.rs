@@ -0,0 +1,27 @@
+use doodle::texture::mip_chain;
+
+fn checker(a: [u8; 4], b: [u8; 4]) -> image::RgbaImage {
+    image::RgbaImage::from_fn(2, 2, |x, y| {
+        image::Rgba(if (x + y) % 2 == 0 { a } else { b })
+    })
+}
+
+#[test]
+fn albedo_mips_average_linear_color() {
+    let data = mip_chain(&checker([0, 0, 0, 255], [255, 255, 255, 255]), true);
+    assert_eq!(data.len(), (4 + 1) * 4);
+    // Half the light of white is much brighter than byte 128 in sRGB
+    let [r, g, b, a] = data[16..].try_into().unwrap();
+    assert_eq!([r, g, b], [188; 3]);
+    assert_eq!(a, 255);
+}
+
+#[test]
+fn normal_map_mips_stay_unit_length() {
+    // Tilted to the left and right by the same amount
+    let data = mip_chain(&checker([51, 128, 230, 255], [204, 128, 230, 255]), false);
+    let texel = &data[16..];
+    assert!(texel[0].abs_diff(128) <= 1, "{:?}", texel);
+    assert!(texel[1].abs_diff(128) <= 1, "{:?}", texel);
+    assert_eq!(texel[2], 255, "{:?}", texel);
+}
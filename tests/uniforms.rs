@@ -0,0 +1,106 @@
+use std::mem::{offset_of, size_of};
+
+use doodle::uniforms::{BlackHoleData, CameraData, Data, EntityData, LightingData};
+
+/// Size and field offsets of a Rust uniform struct, leaving out padding.
+struct Layout {
+    name: &'static str,
+    size: usize,
+    members: Vec<(&'static str, usize)>,
+}
+
+macro_rules! layout {
+    ($ty:ident { $($field:ident),* $(,)? }) => {
+        Layout {
+            name: stringify!($ty),
+            size: size_of::<$ty>(),
+            members: vec![$((stringify!($field), offset_of!($ty, $field))),*],
+        }
+    };
+}
+
+fn shader() -> naga::Module {
+    let source = include_str!("../src/shader.wgsl");
+    let module = naga::front::wgsl::parse_str(source)
+        .unwrap_or_else(|e| panic!("{}", e.emit_to_string(source)));
+    // Validation also rejects structs that break the uniform buffer alignment rules
+    naga::valid::Validator::new(
+        naga::valid::ValidationFlags::all(),
+        naga::valid::Capabilities::empty(),
+    )
+    .validate(&module)
+    .unwrap_or_else(|e| panic!("{}", e.emit_to_string(source)));
+    module
+}
+
+#[test]
+fn uniform_structs_match_the_shader() {
+    let module = shader();
+    let layouts = [
+        layout!(Data {
+            camera,
+            black_hole,
+            lighting,
+        }),
+        layout!(CameraData {
+            proj,
+            proj_inv,
+            view,
+            position,
+            screen_size,
+        }),
+        layout!(BlackHoleData {
+            mass,
+            disk_inner_radius,
+            disk_outer_radius,
+            tolerance,
+            max_steps,
+            spin,
+            inclination,
+            metric,
+            accretion_rate,
+        }),
+        layout!(LightingData {
+            direction,
+            shininess,
+            color,
+            specular_strength,
+            ambient_color,
+            reflectivity,
+        }),
+        layout!(EntityData {
+            model,
+            normal,
+            color,
+        }),
+    ];
+
+    for layout in layouts {
+        let (members, span) = module
+            .types
+            .iter()
+            .find_map(|(_, ty)| match &ty.inner {
+                naga::TypeInner::Struct { members, span }
+                    if ty.name.as_deref() == Some(layout.name) =>
+                {
+                    Some((members, *span))
+                }
+                _ => None,
+            })
+            .unwrap_or_else(|| panic!("shader.wgsl has no struct {}", layout.name));
+
+        let shader_members: Vec<(&str, usize)> = members
+            .iter()
+            .map(|m| (m.name.as_deref().unwrap(), m.offset as usize))
+            .collect();
+        assert_eq!(shader_members, layout.members, "members of {}", layout.name);
+
+        // Rust pads to where the next struct member would start in a uniform buffer
+        assert_eq!(
+            (span as usize).next_multiple_of(16),
+            layout.size,
+            "size of {}",
+            layout.name
+        );
+    }
+}